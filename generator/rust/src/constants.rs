@@ -1,3 +1,8 @@
 pub const MAX_DEPLOY_ARGS: usize = 10;
 pub const MAX_STRING_LEN: usize = 64;
+// The values of a choice, weighted_choice or string_list argument.
+pub const MAX_ARG_VALUES: usize = 16;
+// The longest bech32 address has 90 characters.
+pub const MAX_ADDRESS_LEN: usize = 90;
+pub const MAX_SCRIPT_PUBKEY_LEN: usize = 64;
 pub const MAX_CONTENT_SIZE: usize = 4096;
\ No newline at end of file
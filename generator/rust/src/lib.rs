@@ -75,12 +75,36 @@ pub fn inscribe_generate_rust(input: &[u8]) -> Buffer<MAX_CONTENT_SIZE> {
 
     let mut json_output = deploy_args.args
         .into_iter()
-        .filter(|arg| arg.arg.type_name == "range")
-        .map(|arg| {
-            let range_min = arg.arg.data.min;
-            let range_max = arg.arg.data.max;
-            let random_value = range_min + (hash_value as u64 % (range_max - range_min + 1));
-            (arg.name, Value::UInt(random_value))
+        .filter_map(|arg| {
+            let data = arg.arg.data;
+            let value = match arg.arg.type_name.as_str() {
+                "range" => {
+                    let random_value = data.min + (hash_value as u64 % (data.max - data.min + 1));
+                    Value::UInt(random_value)
+                }
+                "choice" | "enum" => {
+                    let index = hash_value as usize % data.values.len();
+                    Value::String(data.values[index].clone())
+                }
+                "weighted_choice" => {
+                    let total_weight: u64 = data.choices.iter().map(|choice| choice.weight).sum();
+                    let mut point = hash_value as u64 % total_weight;
+                    let choice = data.choices.iter()
+                        .find(|choice| {
+                            if point < choice.weight {
+                                return true;
+                            }
+                            point -= choice.weight;
+                            false
+                        })
+                        .unwrap();
+                    Value::String(choice.value.clone())
+                }
+                "string_list" => Value::StringList(data.values),
+                "bool" => Value::Bool(data.value),
+                _ => return None,
+            };
+            Some((arg.name, value))
         })
         .collect::<LinearMap<_, _, MAX_DEPLOY_ARGS>>();
 
@@ -1,11 +1,9 @@
 use heapless::{String, Vec, LinearMap};
 use minicbor::{Decode, Encode, Decoder, Encoder, decode::Error };
-use super::constants::{MAX_STRING_LEN, MAX_CONTENT_SIZE, MAX_DEPLOY_ARGS };
-
-const MAX_ARGS: usize = 8;
+use super::constants::{MAX_STRING_LEN, MAX_CONTENT_SIZE, MAX_DEPLOY_ARGS, MAX_SCRIPT_PUBKEY_LEN, MAX_ADDRESS_LEN, MAX_ARG_VALUES };
 
 pub struct DeployArgs {
-    pub args: Vec<DeployArg, MAX_ARGS>,
+    pub args: Vec<DeployArg, MAX_DEPLOY_ARGS>,
 }
 
 pub struct DeployArg {
@@ -18,9 +16,24 @@ pub struct Arg {
     pub data: ArgData,
 }
 
+// The data of all the argument types, the fields of the type_name are set.
+// The data may be decoded before the type, so it is decoded by the keys.
+#[derive(Default)]
 pub struct ArgData {
+    // range
     pub min: u64,
     pub max: u64,
+    // choice (enum) and string_list
+    pub values: Vec<String<MAX_STRING_LEN>, MAX_ARG_VALUES>,
+    // weighted_choice
+    pub choices: Vec<WeightedValue, MAX_ARG_VALUES>,
+    // bool
+    pub value: bool,
+}
+
+pub struct WeightedValue {
+    pub value: String<MAX_STRING_LEN>,
+    pub weight: u64,
 }
 
 impl<'a, C> Decode<'a, C> for DeployArgs {
@@ -43,7 +56,7 @@ impl<'a, C> Decode<'a, C> for DeployArg {
       let mut name = String::new();
       let mut arg = Arg {
           type_name: String::new(),
-          data: ArgData { min: 0, max: 0 },
+          data: ArgData::default(),
       };
 
       let len = d.map()?.unwrap_or(0);
@@ -66,7 +79,7 @@ impl<'a, C> Decode<'a, C> for DeployArg {
 impl<'a, C> Decode<'a, C> for Arg {
   fn decode(d: &mut Decoder<'a>, _ctx: &mut C) -> Result<Self, Error> {
       let mut type_name = String::new();
-      let mut data = ArgData { min: 0, max: 0 };
+      let mut data = ArgData::default();
 
       let len = d.map()?.unwrap_or(0);
 
@@ -88,8 +101,45 @@ impl<'a, C> Decode<'a, C> for Arg {
 
 impl<'a, C> Decode<'a, C> for ArgData {
   fn decode(d: &mut Decoder<'a>, _ctx: &mut C) -> Result<Self, Error> {
-      let mut min = 0;
-      let mut max = 0;
+      let mut data = ArgData::default();
+
+      let len = d.map()?.unwrap_or(0);
+
+      for _ in 0..len {
+          let key = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?;
+
+          match key.as_str() {
+              "min" => data.min = d.u64()?,
+              "max" => data.max = d.u64()?,
+              "values" => {
+                  let array_len = d.array()?.unwrap_or(0);
+                  for _ in 0..array_len {
+                      let value = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?;
+                      data.values.push(value).map_err(|_| Error::message("Too many values"))?;
+                  }
+              }
+              "choices" => {
+                  let array_len = d.array()?.unwrap_or(0);
+                  for _ in 0..array_len {
+                      let choice = WeightedValue::decode(d, _ctx)?;
+                      data.choices.push(choice).map_err(|_| Error::message("Too many choices"))?;
+                  }
+              }
+              "value" => data.value = d.bool()?,
+              _ => {
+                  d.skip()?;
+              }
+          }
+      }
+
+      Ok(data)
+  }
+}
+
+impl<'a, C> Decode<'a, C> for WeightedValue {
+  fn decode(d: &mut Decoder<'a>, _ctx: &mut C) -> Result<Self, Error> {
+      let mut value = String::new();
+      let mut weight = 0;
 
       let len = d.map()?.unwrap_or(0);
 
@@ -97,15 +147,15 @@ impl<'a, C> Decode<'a, C> for ArgData {
           let key = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?;
 
           match key.as_str() {
-              "min" => min = d.u64()?,
-              "max" => max = d.u64()?,
+              "value" => value = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?,
+              "weight" => weight = d.u64()?,
               _ => {
                   d.skip()?;
               }
           }
       }
 
-      Ok(ArgData { min, max })
+      Ok(WeightedValue { value, weight })
   }
 }
 
@@ -113,6 +163,11 @@ pub struct InputData {
   pub deploy_args: Vec<u8, MAX_CONTENT_SIZE>,
   pub seed: String<MAX_STRING_LEN>,
   pub user_input: String<MAX_STRING_LEN>,
+  // The ABI version the generator is called with, it is only sent since version 2.
+  pub abi_version: u64,
+  // The address which receives the inscription, since ABI version 2.
+  pub recipient: String<MAX_ADDRESS_LEN>,
+  pub recipient_script_pubkey: Vec<u8, MAX_SCRIPT_PUBKEY_LEN>,
 }
 
 impl<'a, C> Decode<'a, C> for InputData {
@@ -120,6 +175,9 @@ impl<'a, C> Decode<'a, C> for InputData {
       let mut deploy_args = Vec::new();
       let mut seed = String::new();
       let mut user_input = String::new();
+      let mut abi_version = 1;
+      let mut recipient = String::new();
+      let mut recipient_script_pubkey = Vec::new();
 
       let len = d.map()?.unwrap_or(0);
 
@@ -136,6 +194,9 @@ impl<'a, C> Decode<'a, C> for InputData {
               }
               "seed" => seed = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?,
               "user_input" => user_input = String::<MAX_STRING_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid string length"))?,
+              "abi_version" => abi_version = d.u64()?,
+              "recipient" => recipient = String::<MAX_ADDRESS_LEN>::try_from(d.str()?).map_err(|_| Error::message("Invalid address length"))?,
+              "recipient_script_pubkey" => recipient_script_pubkey = Vec::from_slice(d.bytes()?).map_err(|_| Error::message("Recipient script pubkey too large"))?,
               _ => {
                   d.skip()?;
               }
@@ -146,6 +207,9 @@ impl<'a, C> Decode<'a, C> for InputData {
           deploy_args,
           seed,
           user_input,
+          abi_version,
+          recipient,
+          recipient_script_pubkey,
       })
   }
 }
@@ -179,6 +243,7 @@ pub enum Value {
   Int(i64),
   UInt(u64),
   String(String<MAX_STRING_LEN>),
+  StringList(Vec<String<MAX_STRING_LEN>, MAX_ARG_VALUES>),
 }
 
 pub struct OutputData {
@@ -200,8 +265,13 @@ impl<'a, C> Encode<C> for OutputData {
 
       e.str("attributes")?;
       if let Some(ref attributes) = self.attributes {
-          e.map(attributes.len() as u64)?;
-          for (key, value) in attributes {
+          // The keys are encoded in the canonical order of RFC 8949, the same as the inscribed
+          // metadata, so the host can compare the encoded outputs. The shorter keys come first.
+          let mut entries: Vec<(&String<MAX_STRING_LEN>, &Value), MAX_DEPLOY_ARGS> = attributes.iter().collect();
+          entries.sort_unstable_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.as_bytes().cmp(b.as_bytes())));
+
+          e.map(entries.len() as u64)?;
+          for (key, value) in entries {
               e.str(key)?;
 
               match value {
@@ -210,6 +280,13 @@ impl<'a, C> Encode<C> for OutputData {
                 Value::Int(v) => e.i64(*v)?,
                 Value::UInt(v) => e.u64(*v)?,
                 Value::String(v) => e.str(v)?,
+                Value::StringList(v) => {
+                    let e = e.array(v.len() as u64)?;
+                    for item in v {
+                        e.str(item)?;
+                    }
+                    e
+                }
               };
           }
       } else {
@@ -243,6 +320,41 @@ mod tests {
         assert_eq!(decoded_input.deploy_args.len(), 77);
         assert_eq!(decoded_input.seed, "random-seed");
         assert_eq!(decoded_input.user_input, "user-input");
+        assert_eq!(decoded_input.abi_version, 1);
+        assert!(decoded_input.recipient.is_empty());
+    }
+
+    #[test]
+    fn test_input_data_decode_with_recipient() {
+        let script_pubkey = [0xa9, 0x14, 0x0b, 0x8d, 0x2c, 0x87, 0x14, 0x3d, 0x21, 0x9a, 0x4f, 0x51, 0x31, 0x0b, 0x6b, 0x69, 0x57, 0x2a, 0x0a, 0x85, 0x55, 0x8f, 0x87];
+        let mut buf: [u8; MAX_TEST_CONTENT_SIZE] = [0; MAX_TEST_CONTENT_SIZE];
+        let mut e = Encoder::new(buf.as_mut_slice());
+        e.map(4).unwrap()
+            .str("seed").unwrap().str("random-seed").unwrap()
+            .str("abi_version").unwrap().u64(2).unwrap()
+            .str("recipient").unwrap().str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf").unwrap()
+            .str("recipient_script_pubkey").unwrap().bytes(&script_pubkey).unwrap();
+
+        let decoded_input: InputData = minicbor::decode::<InputData>(&buf).unwrap();
+
+        assert_eq!(decoded_input.seed, "random-seed");
+        assert_eq!(decoded_input.abi_version, 2);
+        assert_eq!(decoded_input.recipient, "32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf");
+        assert_eq!(decoded_input.recipient_script_pubkey.as_slice(), &script_pubkey);
+    }
+
+    #[test]
+    fn test_input_data_decode_long_recipient() {
+        let address = "bc1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq";
+        let mut buf: [u8; MAX_TEST_CONTENT_SIZE] = [0; MAX_TEST_CONTENT_SIZE];
+        let mut e = Encoder::new(buf.as_mut_slice());
+        e.map(1).unwrap()
+            .str("recipient").unwrap().str(address).unwrap();
+
+        let decoded_input: InputData = minicbor::decode::<InputData>(&buf).unwrap();
+
+        assert_eq!(address.len(), MAX_ADDRESS_LEN);
+        assert_eq!(decoded_input.recipient, address);
     }
 
     #[test]
@@ -261,6 +373,45 @@ mod tests {
         assert_eq!(decoded.args[1].arg.data.max, 1000);
     }
 
+    #[test]
+    fn test_deploy_args_decode_all_types() {
+      let mut buf: [u8; MAX_TEST_CONTENT_SIZE] = [0; MAX_TEST_CONTENT_SIZE];
+      let mut e = Encoder::new(buf.as_mut_slice());
+      // The host encodes the data before the type.
+      e.array(4).unwrap()
+          .map(1).unwrap().str("color").unwrap()
+              .map(2).unwrap()
+              .str("data").unwrap().map(1).unwrap().str("values").unwrap().array(2).unwrap().str("red").unwrap().str("blue").unwrap()
+              .str("type").unwrap().str("enum").unwrap()
+          .map(1).unwrap().str("rarity").unwrap()
+              .map(2).unwrap()
+              .str("data").unwrap().map(1).unwrap().str("choices").unwrap().array(2).unwrap()
+                  .map(2).unwrap().str("value").unwrap().str("common").unwrap().str("weight").unwrap().u64(90).unwrap()
+                  .map(2).unwrap().str("value").unwrap().str("rare").unwrap().str("weight").unwrap().u64(10).unwrap()
+              .str("type").unwrap().str("weighted_choice").unwrap()
+          .map(1).unwrap().str("names").unwrap()
+              .map(2).unwrap()
+              .str("data").unwrap().map(1).unwrap().str("values").unwrap().array(1).unwrap().str("a").unwrap()
+              .str("type").unwrap().str("string_list").unwrap()
+          .map(1).unwrap().str("animated").unwrap()
+              .map(2).unwrap()
+              .str("data").unwrap().map(1).unwrap().str("value").unwrap().bool(true).unwrap()
+              .str("type").unwrap().str("bool").unwrap();
+
+      let decoded: DeployArgs = minicbor::decode::<DeployArgs>(&buf).unwrap();
+
+      assert_eq!(decoded.args.len(), 4);
+      assert_eq!(decoded.args[0].arg.type_name, "enum");
+      assert_eq!(decoded.args[0].arg.data.values, ["red", "blue"]);
+      assert_eq!(decoded.args[1].arg.type_name, "weighted_choice");
+      assert_eq!(decoded.args[1].arg.data.choices.len(), 2);
+      assert_eq!(decoded.args[1].arg.data.choices[1].value, "rare");
+      assert_eq!(decoded.args[1].arg.data.choices[1].weight, 10);
+      assert_eq!(decoded.args[2].arg.data.values, ["a"]);
+      assert_eq!(decoded.args[3].arg.type_name, "bool");
+      assert!(decoded.args[3].arg.data.value);
+    }
+
     #[test]
     fn test_output_data_encode() {
       let output_data = OutputData {
@@ -282,4 +433,33 @@ mod tests {
       let mut buf: [u8; MAX_TEST_CONTENT_SIZE] = [0; MAX_TEST_CONTENT_SIZE];
       minicbor::encode(&output_data, buf.as_mut_slice()).unwrap();
     }
+
+    #[test]
+    fn test_output_data_encode_canonical_attributes() {
+      let output_data = OutputData {
+          amount: 1,
+          attributes: Some(LinearMap::from_iter([
+            (String::<MAX_STRING_LEN>::try_from("height").unwrap(), Value::UInt(100)),
+            (String::<MAX_STRING_LEN>::try_from("id").unwrap(), Value::UInt(1)),
+            (String::<MAX_STRING_LEN>::try_from("b").unwrap(), Value::Bool(true)),
+          ])),
+          content: None,
+      };
+
+      let mut buf: [u8; MAX_TEST_CONTENT_SIZE] = [0; MAX_TEST_CONTENT_SIZE];
+      minicbor::encode(&output_data, buf.as_mut_slice()).unwrap();
+
+      let mut d = minicbor::Decoder::new(&buf);
+      d.map().unwrap();
+      assert_eq!(d.str().unwrap(), "amount");
+      d.u64().unwrap();
+      assert_eq!(d.str().unwrap(), "attributes");
+      assert_eq!(d.map().unwrap(), Some(3));
+      let mut keys = [""; 3];
+      for key in keys.iter_mut() {
+          *key = d.str().unwrap();
+          d.skip().unwrap();
+      }
+      assert_eq!(keys, ["b", "id", "height"]);
+    }
 }
@@ -0,0 +1,142 @@
+use crate::generator::hash;
+use ciborium::Value;
+use primitive_types::H256;
+
+/// Convert the value to the deterministic form of RFC 8949 section 4.2.1:
+/// the map keys are sorted by the bytewise order of their encodings, recursively.
+/// ciborium already encodes the integers, lengths and floats in the shortest form.
+pub fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Map(map) => {
+            let mut entries: Vec<(Vec<u8>, Value, Value)> = map
+                .into_iter()
+                .map(|(k, v)| {
+                    let k = canonicalize(k);
+                    (to_bytes(&k), k, canonicalize(v))
+                })
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Map(entries.into_iter().map(|(_, k, v)| (k, v)).collect())
+        }
+        Value::Array(array) => Value::Array(array.into_iter().map(canonicalize).collect()),
+        Value::Tag(tag, value) => Value::Tag(tag, Box::new(canonicalize(*value))),
+        value => value,
+    }
+}
+
+/// Sort the keys of the map in the canonical order, the values are kept as they are.
+pub fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Map(map) => {
+            let mut entries: Vec<(Vec<u8>, Value, Value)> =
+                map.into_iter().map(|(k, v)| (to_bytes(&k), k, v)).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Map(entries.into_iter().map(|(_, k, v)| (k, v)).collect())
+        }
+        value => value,
+    }
+}
+
+/// Encode the value to the canonical CBOR bytes.
+pub fn to_canonical_bytes(value: &Value) -> Vec<u8> {
+    to_bytes(&canonicalize(value.clone()))
+}
+
+/// Compare the values regardless of the order of the map keys.
+pub fn canonical_eq(a: &Value, b: &Value) -> bool {
+    to_canonical_bytes(a) == to_canonical_bytes(b)
+}
+
+/// Compare the optional values regardless of the order of the map keys.
+pub fn canonical_eq_opt(a: &Option<Value>, b: &Option<Value>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => canonical_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The sha3 hash of the canonical CBOR bytes, it does not depend on the order of the map keys.
+pub fn canonical_hash(value: &Value) -> H256 {
+    hash::sha3_256_of(&to_canonical_bytes(value))
+}
+
+fn to_bytes(value: &Value) -> Vec<u8> {
+    let mut buffer = vec![];
+    ciborium::into_writer(value, &mut buffer).expect("ciborium marshal failed");
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let value = Value::Map(vec![
+            (text("height"), Value::Integer(100.into())),
+            (text("id"), text("bits")),
+            (
+                text("b"),
+                Value::Map(vec![
+                    (text("y"), Value::Bool(true)),
+                    (text("x"), Value::Null),
+                ]),
+            ),
+        ]);
+
+        // The shorter keys are encoded with a smaller length prefix, so they come first.
+        assert_eq!(
+            canonicalize(value.clone()),
+            Value::Map(vec![
+                (
+                    text("b"),
+                    Value::Map(vec![
+                        (text("x"), Value::Null),
+                        (text("y"), Value::Bool(true))
+                    ]),
+                ),
+                (text("id"), text("bits")),
+                (text("height"), Value::Integer(100.into())),
+            ])
+        );
+
+        // Only the top level keys are sorted, the nested map keeps its order.
+        assert_eq!(
+            sort_keys(value.clone()),
+            Value::Map(vec![
+                (
+                    text("b"),
+                    Value::Map(vec![
+                        (text("y"), Value::Bool(true)),
+                        (text("x"), Value::Null)
+                    ]),
+                ),
+                (text("id"), text("bits")),
+                (text("height"), Value::Integer(100.into())),
+            ])
+        );
+
+        let reordered = Value::Map(vec![
+            (
+                text("b"),
+                Value::Map(vec![
+                    (text("x"), Value::Null),
+                    (text("y"), Value::Bool(true)),
+                ]),
+            ),
+            (text("height"), Value::Integer(100.into())),
+            (text("id"), text("bits")),
+        ]);
+        assert_ne!(value, reordered);
+        assert!(canonical_eq(&value, &reordered));
+        assert_eq!(canonical_hash(&value), canonical_hash(&reordered));
+
+        let other = Value::Map(vec![(text("height"), Value::Integer(101.into()))]);
+        assert!(!canonical_eq(&value, &other));
+    }
+}
@@ -4,6 +4,7 @@ use crate::operation::deploy_args_cbor_encode;
 use crate::wallet::Wallet;
 use crate::SubcommandResult;
 
+use anyhow::bail;
 use clap::Parser;
 use ord::InscriptionId;
 
@@ -18,17 +19,29 @@ pub struct DeployCommand {
     #[arg(long, help = "The generator Inscription id on Bitcoin.")]
     generator: Option<InscriptionId>,
 
-    #[arg(long, help = "The mint factory name.")]
+    #[arg(long, help = "The built-in mint factory name: fixed, random or pow.")]
     factory: Option<String>,
 
     #[arg(
         long,
-        help = "The number of allowed the SFT attributes repeats. 0 means do not limit.",
+        help = "The maximum number of SFTs minted with the same attributes, 1 makes the attributes unique. 0 means do not limit.",
         default_value = "0"
     )]
     repeat: u64,
 
-    #[arg(long, help = "The deploy arguments to the generator program.")]
+    #[arg(long, help = "Whether the mint requires the user input.")]
+    has_user_input: bool,
+
+    #[arg(
+        long,
+        help = "Inscribe the deploy as a child of the generator inscription."
+    )]
+    parent: bool,
+
+    #[arg(
+        long,
+        help = "The deploy arguments to the generator program or the factory."
+    )]
     deploy_args: Vec<String>,
 
     #[clap(flatten)]
@@ -37,21 +50,27 @@ pub struct DeployCommand {
 
 impl DeployCommand {
     pub fn run(self, wallet: Wallet) -> SubcommandResult {
-        //TODO how to encode the factory args.
-        let deploy_args = deploy_args_cbor_encode(self.deploy_args);
-
-        //TODO check the tick name is valid
-        let tick = self.tick.to_uppercase();
-        let output = Inscriber::new(wallet, self.inscribe_options)?
-            .with_deploy(
-                tick,
-                self.amount,
-                self.generator,
-                self.factory,
-                self.repeat,
-                deploy_args,
-            )?
-            .inscribe()?;
+        let deploy_args = deploy_args_cbor_encode(self.deploy_args)?;
+
+        let parent = match (self.parent, self.generator) {
+            (true, Some(generator)) => Some(generator),
+            (true, None) => bail!("--parent requires the deploy to have a --generator"),
+            (false, _) => None,
+        };
+
+        let mut inscriber = Inscriber::new(wallet, self.inscribe_options)?.with_deploy(
+            self.tick,
+            self.amount,
+            self.generator,
+            self.factory,
+            self.repeat,
+            self.has_user_input,
+            deploy_args,
+        )?;
+        if let Some(parent) = parent {
+            inscriber = inscriber.with_parent(parent);
+        }
+        let output = inscriber.inscribe()?;
         Ok(Box::new(output))
     }
 }
@@ -1,3 +1,5 @@
+use crate::compression::Compression;
+use crate::generator::metadata::{GeneratorMetadata, ABI_VERSION};
 use crate::inscribe::InscribeOptions;
 use crate::inscribe::Inscriber;
 use crate::wallet::Wallet;
@@ -12,14 +14,37 @@ pub struct GeneratorCommand {
     name: String,
     #[arg(long, help = "Path to the generator bytecode file.")]
     generator: PathBuf,
+    #[arg(long, help = "The semantic version of the generator, e.g. 1.0.0.")]
+    generator_version: Option<String>,
+    #[arg(long, help = "Description of the generator.")]
+    description: Option<String>,
+    #[arg(long, help = "Author of the generator.")]
+    author: Option<String>,
+    #[arg(
+        long,
+        help = "The ABI version of the generator.",
+        default_value_t = ABI_VERSION
+    )]
+    abi_version: u64,
+    #[arg(long, help = "Compress the generator bytecode with <COMPRESSION>.")]
+    compression: Option<Compression>,
     #[clap(flatten)]
     inscribe_options: InscribeOptions,
 }
 
 impl GeneratorCommand {
     pub fn run(self, wallet: Wallet) -> SubcommandResult {
+        let metadata = GeneratorMetadata {
+            name: self.name,
+            abi_version: self.abi_version,
+            version: self.generator_version,
+            description: self.description,
+            author: self.author,
+            // The hash is computed from the bytecode file.
+            hash: None,
+        };
         let output = Inscriber::new(wallet, self.inscribe_options)?
-            .with_generator(self.name, self.generator)?
+            .with_generator(metadata, self.generator, self.compression)?
             .inscribe()?;
 
         Ok(Box::new(output))
@@ -0,0 +1,20 @@
+use crate::indexer::{Indexer, SFTEntry};
+use crate::wallet::Wallet;
+use crate::SubcommandResult;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ListCommand {}
+
+impl ListCommand {
+    pub fn run(self, wallet: Wallet) -> SubcommandResult {
+        let unspent_outputs = wallet.get_unspent_outputs()?;
+
+        Indexer::sync(&wallet)?;
+        let sfts: Vec<SFTEntry> = wallet
+            .store()?
+            .get_sfts(|entry| unspent_outputs.contains_key(&entry.satpoint.outpoint))?;
+
+        Ok(Box::new(sfts))
+    }
+}
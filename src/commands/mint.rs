@@ -13,15 +13,21 @@ pub struct MintCommand {
     #[arg(long, help = "The user input argument to the generator.")]
     user_input: Option<String>,
 
+    #[arg(long, help = "Inscribe the mint as a child of the deploy inscription.")]
+    parent: bool,
+
     #[clap(flatten)]
     inscribe_options: InscribeOptions,
 }
 
 impl MintCommand {
     pub fn run(self, wallet: Wallet) -> SubcommandResult {
-        let output = Inscriber::new(wallet, self.inscribe_options)?
-            .with_mint(self.deploy_inscription_id, self.user_input)?
-            .inscribe()?;
+        let mut inscriber = Inscriber::new(wallet, self.inscribe_options)?
+            .with_mint(self.deploy_inscription_id, self.user_input)?;
+        if self.parent {
+            inscriber = inscriber.with_parent(self.deploy_inscription_id);
+        }
+        let output = inscriber.inscribe()?;
         Ok(Box::new(output))
     }
 }
@@ -1,6 +1,9 @@
 pub mod deploy;
 pub mod generator;
+pub mod list;
 pub mod merge;
 pub mod mint;
+pub mod reindex;
+pub mod server;
 pub mod split;
 pub mod view;
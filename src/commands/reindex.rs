@@ -0,0 +1,29 @@
+use crate::indexer::Indexer;
+use crate::store::SCHEMA_VERSION;
+use crate::wallet::Wallet;
+use crate::SubcommandResult;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Parser)]
+pub struct ReindexCommand {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexOutput {
+    pub schema_version: u64,
+    pub height: Option<u64>,
+}
+
+impl ReindexCommand {
+    pub fn run(self, wallet: Wallet) -> SubcommandResult {
+        wallet.reindex_store()?;
+
+        let mut indexer = Indexer::new(wallet)?;
+        indexer.index_to_tip()?;
+
+        Ok(Box::new(ReindexOutput {
+            schema_version: SCHEMA_VERSION,
+            height: indexer.state().height(),
+        }))
+    }
+}
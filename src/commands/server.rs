@@ -0,0 +1,72 @@
+use crate::indexer::{Indexer, IndexerState};
+use crate::server::{router, ServerState};
+use crate::wallet::Wallet;
+use crate::SubcommandResult;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[derive(Debug, Parser)]
+pub struct ServerCommand {
+    #[arg(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "Listen on <ADDRESS> for the HTTP requests."
+    )]
+    address: SocketAddr,
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Index the new blocks every <POLLING_INTERVAL> seconds."
+    )]
+    polling_interval: u64,
+}
+
+impl ServerCommand {
+    pub fn run(self, wallet: Wallet) -> SubcommandResult {
+        // The indexer works on the served state, its own state is only a placeholder.
+        let server_state = Arc::new(ServerState::new(wallet.store()?.load_state()?));
+        let mut indexer = Indexer::with_state(wallet, IndexerState::new());
+        server_state.index(&mut indexer)?;
+
+        let listener = TcpListener::bind(self.address)?;
+        info!("listening on http://{}", self.address);
+        let server = {
+            let server_state = server_state.clone();
+            thread::spawn(move || serve(listener, server_state))
+        };
+
+        // The indexer uses the blocking bitcoind client, so it runs on this thread
+        // and the HTTP server runs on its own runtime.
+        loop {
+            thread::sleep(Duration::from_secs(self.polling_interval));
+
+            if server.is_finished() {
+                server
+                    .join()
+                    .map_err(|_| anyhow!("The HTTP server panicked"))??;
+                return Err(anyhow!("The HTTP server stopped"));
+            }
+
+            if let Err(e) = server_state.index(&mut indexer) {
+                warn!("failed to index the new blocks: {}", e);
+            }
+        }
+    }
+}
+
+fn serve(listener: TcpListener, server_state: Arc<ServerState>) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async move {
+        axum::Server::from_tcp(listener)?
+            .serve(router(server_state).into_make_service())
+            .await?;
+        Ok::<(), anyhow::Error>(())
+    })
+}
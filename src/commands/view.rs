@@ -1,11 +1,16 @@
+use crate::indexer::Indexer;
+use crate::sft::SFT;
+use crate::store::Store;
 use crate::wallet::Wallet;
 use crate::SubcommandResult;
+use ciborium::Value;
 use clap::Parser;
 use ord::InscriptionId;
+use serde::{Deserialize, Serialize};
 
 use {
     crate::operation::{AsSFT, Operation},
-    anyhow::bail,
+    anyhow::{bail, Result},
 };
 
 #[derive(Debug, Parser)]
@@ -14,19 +19,140 @@ pub struct ViewCommand {
     sft_inscription_id: InscriptionId,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ViewOutput {
+    Valid {
+        #[serde(flatten)]
+        sft: SFT,
+        indexer_attributes: Option<Value>,
+        // The SFTs which are burned to create the SFT by split or merge
+        lineage: Option<Vec<InscriptionId>>,
+    },
+    // The indexer rejected the inscription, e.g. a mint which does not match the generator
+    Invalid {
+        inscription_id: InscriptionId,
+        reason: String,
+    },
+}
+
 impl ViewCommand {
     pub fn run(self, wallet: Wallet) -> SubcommandResult {
-        let operation = wallet.get_operation_by_inscription_id(self.sft_inscription_id)?;
-        let sft = match operation {
-            Operation::Mint(mint_record) => mint_record.as_sft(),
-            Operation::Split(split_record) => split_record.as_sft(),
-            Operation::Merge(merge_record) => merge_record.as_sft(),
-            _ => bail!(
-                "Inscription {} is not a valid SFT record",
-                self.sft_inscription_id
-            ),
-        };
-
-        Ok(Box::new(sft))
+        Indexer::sync(&wallet)?;
+        let output = view(wallet.store()?, self.sft_inscription_id)?;
+        Ok(Box::new(output))
+    }
+}
+
+/// The SFT of the inscription in the index. The operations are stored before they are
+/// validated, so the invalid state is checked first.
+fn view(store: &Store, inscription_id: InscriptionId) -> Result<ViewOutput> {
+    if let Some(reason) = store.get_invalid(&inscription_id)? {
+        return Ok(ViewOutput::Invalid {
+            inscription_id,
+            reason,
+        });
+    }
+
+    let lineage = store.get_lineage(&inscription_id)?;
+    if let Some(entry) = store.get_sft(&inscription_id)? {
+        return Ok(ViewOutput::Valid {
+            sft: entry.sft,
+            indexer_attributes: entry.indexer_attributes,
+            lineage,
+        });
+    }
+
+    // The SFT is burned by a later split or merge, only its operation is kept.
+    let sft = match store.get_operation(&inscription_id)? {
+        Some(Operation::Mint(mint_record)) => mint_record.as_sft(),
+        Some(Operation::Split(split_record)) => split_record.as_sft(),
+        Some(Operation::Merge(merge_record)) => merge_record.as_sft(),
+        Some(_) => bail!("Inscription {} is not a valid SFT record", inscription_id),
+        None => bail!("Inscription {} is not found in the index", inscription_id),
+    };
+    Ok(ViewOutput::Valid {
+        sft,
+        indexer_attributes: None,
+        lineage,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::SFTEntry;
+    use crate::operation::MintRecord;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, OutPoint};
+    use ordinals::SatPoint;
+    use std::str::FromStr;
+
+    fn inscription_id(index: u32) -> InscriptionId {
+        InscriptionId::from_str(&format!(
+            "6f55475ce65054aa8371d618d217da8c9a764cecdaf4debcbce8d6312fe6b4d8i{}",
+            index
+        ))
+        .unwrap()
+    }
+
+    fn mint(amount: u64) -> Operation {
+        Operation::Mint(MintRecord {
+            sft: SFT::new("BITS".to_string(), amount, None, None),
+            user_input: None,
+        })
+    }
+
+    #[test]
+    fn test_view_forged_mint() {
+        let dir = std::env::temp_dir().join(format!("bitseed-view-{}", uuid::Uuid::new_v4()));
+        let store = Store::open(&dir.join("index.redb")).unwrap();
+        let mut state = store.load_state().unwrap();
+
+        // The forged mint is decoded and stored, but the generator rejects it.
+        let forged = inscription_id(0);
+        state.insert_operation(forged, mint(1_000_000));
+        state.insert_invalid(
+            forged,
+            "The mint output does not match the generator output of tick BITS".to_string(),
+        );
+
+        let valid = inscription_id(1);
+        state.insert_operation(valid, mint(1000));
+        state.insert_sft(SFTEntry {
+            inscription_id: valid,
+            sft: SFT::new("BITS".to_string(), 1000, None, None),
+            indexer_attributes: None,
+            satpoint: SatPoint {
+                outpoint: OutPoint {
+                    txid: valid.txid,
+                    vout: 0,
+                },
+                offset: 0,
+            },
+            owner: None,
+        });
+
+        state.commit_block(1, BlockHash::hash(&1u64.to_le_bytes()), 6);
+        let changes = state.take_changes();
+        store.save_changes(None, &state, changes).unwrap();
+
+        match view(&store, forged).unwrap() {
+            ViewOutput::Invalid {
+                inscription_id,
+                reason,
+            } => {
+                assert_eq!(inscription_id, forged);
+                assert!(reason.contains("does not match the generator output"));
+            }
+            output => panic!("The forged mint is shown as {:?}", output),
+        }
+        match view(&store, valid).unwrap() {
+            ViewOutput::Valid { sft, .. } => assert_eq!(sft.amount, 1000),
+            output => panic!("The valid mint is shown as {:?}", output),
+        }
+        assert!(view(&store, inscription_id(2)).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,100 @@
+use anyhow::{bail, ensure, Result};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// The content encoding of an inscription body, the names are the HTTP `Content-Encoding` tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    Brotli,
+    Gzip,
+}
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::Brotli => "br",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        match self {
+            Compression::Brotli => {
+                // `CompressorWriter::into_inner` ignores the errors of finishing the stream.
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    lgwin: 22,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut compressed, &params)?;
+            }
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(compressed)
+    }
+
+    /// Decompress the data, fails if the decompressed data is larger than `limit` bytes,
+    /// so a small body can not expand to exhaust the memory.
+    pub fn decompress(&self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let reader: Box<dyn Read + '_> = match self {
+            Compression::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+            Compression::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+        };
+        let mut decompressed = Vec::new();
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut decompressed)?;
+        ensure!(
+            decompressed.len() as u64 <= limit,
+            "The decompressed content exceeds the limit of {} bytes",
+            limit
+        );
+        Ok(decompressed)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "br" => Ok(Compression::Brotli),
+            "gzip" => Ok(Compression::Gzip),
+            _ => bail!("Unsupported content encoding: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let data = vec![7u8; 64 * 1024];
+        for compression in [Compression::Brotli, Compression::Gzip] {
+            let compressed = compression.compress(&data).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(
+                Compression::from_str(compression.as_str()).unwrap(),
+                compression
+            );
+            assert_eq!(
+                compression
+                    .decompress(&compressed, data.len() as u64)
+                    .unwrap(),
+                data
+            );
+            assert!(compression
+                .decompress(&compressed, data.len() as u64 - 1)
+                .is_err());
+        }
+        assert!(Compression::from_str("deflate").is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use crate::error::{BitseedError, BitseedResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JSONValue};
+use std::collections::BTreeSet;
+
+/// The limits of the generator, they must be the same as generator/rust/src/constants.rs.
+/// A deploy with more args is rejected, since the generator can not decode it.
+pub const MAX_DEPLOY_ARGS: usize = 10;
+pub const MAX_STRING_LEN: usize = 64;
+pub const MAX_ARG_VALUES: usize = 16;
+
+/// The deploy arguments passed to the generator, encoded as a CBOR array of
+/// `{"<name>": {"type": "<type>", "data": <data>}}` maps.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeployArgs {
+    pub args: Vec<DeployArg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeployArg {
+    pub name: String,
+    pub arg: Arg,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Arg {
+    /// A random integer in [min, max].
+    Range { min: u64, max: u64 },
+    /// One of the values with the same probability.
+    #[serde(alias = "enum")]
+    Choice { values: Vec<String> },
+    /// One of the values with the probability proportional to its weight.
+    WeightedChoice { choices: Vec<WeightedValue> },
+    /// A static list of strings.
+    StringList { values: Vec<String> },
+    /// A static flag.
+    Bool { value: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightedValue {
+    pub value: String,
+    pub weight: u64,
+}
+
+impl Arg {
+    pub fn validate(&self) -> BitseedResult<()> {
+        match self {
+            Arg::Range { min, max } => {
+                if min > max {
+                    return Err(invalid(format!(
+                        "range min {} is greater than max {}",
+                        min, max
+                    )));
+                }
+            }
+            Arg::Choice { values } | Arg::StringList { values } => {
+                if values.is_empty() {
+                    return Err(invalid("the values are empty"));
+                }
+                check_len(values.len())?;
+                for value in values {
+                    check_string(value)?;
+                }
+            }
+            Arg::WeightedChoice { choices } => {
+                if choices.is_empty() {
+                    return Err(invalid("the choices are empty"));
+                }
+                check_len(choices.len())?;
+                let mut total_weight = 0u64;
+                for choice in choices {
+                    check_string(&choice.value)?;
+                    total_weight = total_weight
+                        .checked_add(choice.weight)
+                        .ok_or_else(|| invalid("the total weight overflows"))?;
+                }
+                if total_weight == 0 {
+                    return Err(invalid("the total weight is zero"));
+                }
+            }
+            Arg::Bool { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+impl DeployArgs {
+    /// Parse the deploy arguments from the JSON strings given on the command line,
+    /// each string is a `{"<name>": {"type": "<type>", "data": <data>}}` object.
+    pub fn from_json_strings(deploy_args: &[String]) -> BitseedResult<Self> {
+        let values = deploy_args
+            .iter()
+            .map(|arg| {
+                serde_json::from_str(arg)
+                    .map_err(|e| invalid(format!("{} is not a valid JSON: {}", arg, e)))
+            })
+            .collect::<BitseedResult<Vec<JSONValue>>>()?;
+        Self::from_json(JSONValue::Array(values))
+    }
+
+    pub fn from_json(value: JSONValue) -> BitseedResult<Self> {
+        let values = match value {
+            JSONValue::Array(values) => values,
+            _ => return Err(invalid("the deploy args are not an array")),
+        };
+
+        let mut args = Vec::with_capacity(values.len());
+        for value in values {
+            let map = match value {
+                JSONValue::Object(map) if map.len() == 1 => map,
+                _ => return Err(invalid(format!("{} is not a single named argument", value))),
+            };
+            let (name, arg) = map.into_iter().next().expect("the map has one entry");
+            let arg = serde_json::from_value(arg)
+                .map_err(|e| invalid(format!("invalid argument {}: {}", name, e)))?;
+            args.push(DeployArg { name, arg });
+        }
+
+        let deploy_args = Self { args };
+        deploy_args.validate()?;
+        Ok(deploy_args)
+    }
+
+    pub fn to_json(&self) -> JSONValue {
+        JSONValue::Array(
+            self.args
+                .iter()
+                .map(|arg| {
+                    let mut map = Map::new();
+                    map.insert(
+                        arg.name.clone(),
+                        serde_json::to_value(&arg.arg).expect("the arg is serializable"),
+                    );
+                    JSONValue::Object(map)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> BitseedResult<Self> {
+        let value: JSONValue =
+            ciborium::from_reader(bytes).map_err(|e| invalid(format!("invalid CBOR: {}", e)))?;
+        Self::from_json(value)
+    }
+
+    pub fn to_cbor(&self) -> BitseedResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        ciborium::into_writer(&self.to_json(), &mut buffer)
+            .map_err(|e| BitseedError::InvalidDeployArgs(e.to_string()))?;
+        Ok(buffer)
+    }
+
+    pub fn validate(&self) -> BitseedResult<()> {
+        if self.args.len() > MAX_DEPLOY_ARGS {
+            return Err(invalid(format!(
+                "{} arguments exceed the limit {}",
+                self.args.len(),
+                MAX_DEPLOY_ARGS
+            )));
+        }
+
+        let mut names = BTreeSet::new();
+        for arg in self.args.iter() {
+            if arg.name.is_empty() {
+                return Err(invalid("the argument name is empty"));
+            }
+            check_string(&arg.name)?;
+            if !names.insert(arg.name.as_str()) {
+                return Err(invalid(format!("duplicate argument {}", arg.name)));
+            }
+            arg.arg.validate().map_err(|e| match e {
+                BitseedError::InvalidDeployArgs(reason) => {
+                    invalid(format!("invalid argument {}: {}", arg.name, reason))
+                }
+                e => e,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn check_len(len: usize) -> BitseedResult<()> {
+    if len > MAX_ARG_VALUES {
+        return Err(invalid(format!(
+            "{} values exceed the limit {}",
+            len, MAX_ARG_VALUES
+        )));
+    }
+    Ok(())
+}
+
+fn check_string(value: &str) -> BitseedResult<()> {
+    if value.len() > MAX_STRING_LEN {
+        return Err(invalid(format!(
+            "{:?} is longer than {} bytes",
+            value, MAX_STRING_LEN
+        )));
+    }
+    Ok(())
+}
+
+fn invalid<S: ToString>(reason: S) -> BitseedError {
+    BitseedError::InvalidDeployArgs(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> BitseedResult<DeployArgs> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        DeployArgs::from_json_strings(&args)
+    }
+
+    #[test]
+    fn test_deploy_args_round_trip() {
+        let deploy_args = parse(&[
+            r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#,
+            r#"{"color":{"type":"enum","data":{"values":["red","blue"]}}}"#,
+            r#"{"rarity":{"type":"weighted_choice","data":{"choices":[{"value":"common","weight":90},{"value":"rare","weight":10}]}}}"#,
+            r#"{"names":{"type":"string_list","data":{"values":["a","b"]}}}"#,
+            r#"{"animated":{"type":"bool","data":{"value":true}}}"#,
+        ])
+        .unwrap();
+
+        assert_eq!(deploy_args.args.len(), 5);
+        assert_eq!(deploy_args.args[0].arg, Arg::Range { min: 1, max: 1000 });
+        assert_eq!(
+            deploy_args.args[1].arg,
+            Arg::Choice {
+                values: vec!["red".to_string(), "blue".to_string()]
+            }
+        );
+
+        let cbor = deploy_args.to_cbor().unwrap();
+        assert_eq!(DeployArgs::from_cbor(&cbor).unwrap(), deploy_args);
+        assert_eq!(
+            DeployArgs::from_json(deploy_args.to_json()).unwrap(),
+            deploy_args
+        );
+    }
+
+    #[test]
+    fn test_invalid_deploy_args() {
+        assert!(parse(&["{height"]).is_err());
+        assert!(parse(&[r#"{"height":{"type":"range"}}"#]).is_err());
+        assert!(parse(&[r#"{"height":{"type":"range","data":{"min":10,"max":1}}}"#]).is_err());
+        assert!(parse(&[r#"{"height":{"type":"unknown","data":{}}}"#]).is_err());
+        assert!(parse(&[r#"{"color":{"type":"choice","data":{"values":[]}}}"#]).is_err());
+        assert!(parse(&[
+            r#"{"rarity":{"type":"weighted_choice","data":{"choices":[{"value":"common","weight":0}]}}}"#
+        ])
+        .is_err());
+        assert!(parse(&[
+            r#"{"height":{"type":"range","data":{"min":1,"max":2}}}"#,
+            r#"{"height":{"type":"range","data":{"min":1,"max":3}}}"#,
+        ])
+        .is_err());
+        assert!(parse(&[r#"{"a":{"type":"bool","data":{"value":true}},"b":{"type":"bool","data":{"value":false}}}"#]).is_err());
+    }
+
+    #[test]
+    fn test_max_deploy_args() {
+        let args = |count: usize| {
+            (0..count)
+                .map(|index| {
+                    format!(
+                        r#"{{"arg{}":{{"type":"bool","data":{{"value":true}}}}}}"#,
+                        index
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(DeployArgs::from_json_strings(&args(MAX_DEPLOY_ARGS)).is_ok());
+        assert!(DeployArgs::from_json_strings(&args(MAX_DEPLOY_ARGS + 1)).is_err());
+
+        let choice = |count: usize| {
+            let values = (0..count)
+                .map(|index| format!(r#""v{}""#, index))
+                .collect::<Vec<_>>()
+                .join(",");
+            parse(&[&format!(
+                r#"{{"color":{{"type":"choice","data":{{"values":[{}]}}}}}}"#,
+                values
+            )])
+        };
+        assert!(choice(MAX_ARG_VALUES).is_ok());
+        assert!(choice(MAX_ARG_VALUES + 1).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use thiserror::Error;
+
+/// The errors of decoding and encoding the Bitseed inscriptions.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BitseedError {
+    /// The inscription does not use the Bitseed metaprotocol, it should be ignored.
+    #[error("not a bitseed inscription: {0}")]
+    NotBitseed(String),
+    #[error("metadata not found")]
+    MissingMetadata,
+    #[error("key ({0:?}) not found in metadata")]
+    MissingField(String),
+    #[error("invalid {field}: {reason}")]
+    InvalidField { field: String, reason: String },
+    #[error("unknown op: {0}")]
+    UnsupportedOp(String),
+    #[error("invalid deploy args: {0}")]
+    InvalidDeployArgs(String),
+}
+
+impl BitseedError {
+    pub fn invalid_field<F: ToString, R: ToString>(field: F, reason: R) -> Self {
+        BitseedError::InvalidField {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// A malformed inscription uses the Bitseed metaprotocol but can not be decoded.
+    pub fn is_malformed(&self) -> bool {
+        !matches!(self, BitseedError::NotBitseed(_))
+    }
+
+    /// A short name of the error kind, for classifying and counting the invalid inscriptions.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BitseedError::NotBitseed(_) => "not_bitseed",
+            BitseedError::MissingMetadata => "missing_metadata",
+            BitseedError::MissingField(_) => "missing_field",
+            BitseedError::InvalidField { .. } => "invalid_field",
+            BitseedError::UnsupportedOp(_) => "unsupported_op",
+            BitseedError::InvalidDeployArgs(_) => "invalid_deploy_args",
+        }
+    }
+}
+
+pub type BitseedResult<T> = std::result::Result<T, BitseedError>;
+
+/// The errors of running a generator. A mint whose generator fails is invalid,
+/// the indexer records it and keeps running.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GeneratorError {
+    /// The bytecode can not be compiled or instantiated, e.g. it exceeds the memory limit.
+    #[error("failed to instantiate the generator: {0}")]
+    Instantiation(String),
+    #[error("the generator does not export {0}")]
+    MissingExport(String),
+    #[error("the generator trapped: {0}")]
+    Trap(String),
+    #[error("the generator ran out of fuel, the limit is {limit}")]
+    OutOfFuel { limit: u64 },
+    #[error("invalid generator output: {0}")]
+    BadOutput(String),
+    #[error("the generator memory access is out of bounds: {0}")]
+    OutOfBounds(String),
+}
+
+impl GeneratorError {
+    pub fn bad_output<R: ToString>(reason: R) -> Self {
+        GeneratorError::BadOutput(reason.to_string())
+    }
+
+    /// A short name of the error kind, for classifying and counting the failed generator calls.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GeneratorError::Instantiation(_) => "instantiation",
+            GeneratorError::MissingExport(_) => "missing_export",
+            GeneratorError::Trap(_) => "trap",
+            GeneratorError::OutOfFuel { .. } => "out_of_fuel",
+            GeneratorError::BadOutput(_) => "bad_output",
+            GeneratorError::OutOfBounds(_) => "out_of_bounds",
+        }
+    }
+}
+
+pub type GeneratorResult<T> = std::result::Result<T, GeneratorError>;
+
+/// An operation which breaks a protocol rule, the indexer records it as invalid and keeps running.
+/// The other errors, e.g. of bitcoind or the store, abort the block so it is indexed again.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{0}")]
+pub struct InvalidOperation(pub String);
+
+impl InvalidOperation {
+    pub fn new<R: ToString>(reason: R) -> Self {
+        InvalidOperation(reason.to_string())
+    }
+}
+
+/// Mark the error of a protocol rule check as an invalid operation.
+pub fn invalid(e: anyhow::Error) -> anyhow::Error {
+    InvalidOperation(format!("{:#}", e)).into()
+}
+
+/// Whether the error makes the operation invalid. The decoding and generator errors are caused
+/// by the inscriptions, so they are protocol failures too.
+pub fn is_invalid(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.is::<InvalidOperation>() || cause.is::<BitseedError>() || cause.is::<GeneratorError>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_is_invalid() {
+        assert!(is_invalid(&invalid(anyhow!("tick BITS is not deployed"))));
+        assert!(is_invalid(&anyhow::Error::from(
+            BitseedError::MissingMetadata
+        )));
+        assert!(is_invalid(
+            &anyhow::Error::from(GeneratorError::OutOfFuel { limit: 1 }).context("mint")
+        ));
+        assert!(!is_invalid(&anyhow!("connection refused")));
+        assert!(!is_invalid(&anyhow::Error::from(std::io::Error::other(
+            "disk full"
+        ))));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::deploy_args::{Arg, DeployArgs};
+use crate::error::{GeneratorError, GeneratorResult};
+use crate::generator::mock::random_amount_generator::RandomAmountGenerator;
+use crate::generator::{hash, Generator, InscribeGenerateOutput, InscribeSeed};
+use anyhow::{anyhow, bail, ensure, Result};
+use bitcoin::Address;
+use ciborium::Value;
+use primitive_types::H256;
+
+/// Mint a fixed `amount` for each mint.
+pub const FIXED: &str = "fixed";
+/// Mint a uniform random amount in the `amount` range.
+pub const RANDOM: &str = "random";
+/// Mint a fixed `amount` with a proof of work of `difficulty` leading zero bits.
+pub const POW: &str = "pow";
+
+pub const FACTORIES: [&str; 3] = [FIXED, RANDOM, POW];
+
+/// The work of a mint is bounded, the indexer may need to redo it.
+pub const MAX_POW_DIFFICULTY: u64 = 24;
+/// The nonces tried for a mint, 16 times the expected work of the max difficulty. A mint which
+/// finds no nonce fails, it can be retried with another seed utxo.
+pub const MAX_POW_ATTEMPTS: u64 = 1 << 28;
+
+const NONCE: &str = "nonce";
+
+/// Load the built-in factory by name, configured with the deploy args.
+/// The factories are native generators, so a tick can be deployed without publishing a WASM generator.
+pub fn load(name: &str, deploy_args: &[u8]) -> Result<Box<dyn Generator>> {
+    let args = DeployArgs::from_cbor(deploy_args)?;
+    let generator: Box<dyn Generator> = match name {
+        FIXED => Box::new(FixedAmountFactory {
+            amount: fixed_arg(&args, "amount")?,
+        }),
+        RANDOM => {
+            let (min, max) = range_arg(&args, "amount")?;
+            Box::new(RandomAmountGenerator::new(min, max))
+        }
+        POW => {
+            let difficulty = fixed_arg(&args, "difficulty")?;
+            ensure!(
+                difficulty > 0 && difficulty <= MAX_POW_DIFFICULTY,
+                "The pow difficulty must be in [1, {}], got {}",
+                MAX_POW_DIFFICULTY,
+                difficulty
+            );
+            Box::new(PowFactory {
+                amount: fixed_arg(&args, "amount")?,
+                difficulty: difficulty as u32,
+            })
+        }
+        _ => bail!(
+            "Unknown factory {:?}, the factories are {:?}",
+            name,
+            FACTORIES
+        ),
+    };
+    Ok(generator)
+}
+
+fn range_arg(args: &DeployArgs, name: &str) -> Result<(u64, u64)> {
+    let arg = args
+        .args
+        .iter()
+        .find(|arg| arg.name == name)
+        .ok_or_else(|| anyhow!("The factory requires the deploy arg {}", name))?;
+    match arg.arg {
+        Arg::Range { min, max } => Ok((min, max)),
+        _ => bail!("The deploy arg {} must be a range", name),
+    }
+}
+
+/// A single integer is given as a range with the same min and max.
+fn fixed_arg(args: &DeployArgs, name: &str) -> Result<u64> {
+    let (min, max) = range_arg(args, name)?;
+    ensure!(
+        min == max,
+        "The deploy arg {} must be a range with the same min and max",
+        name
+    );
+    Ok(min)
+}
+
+pub struct FixedAmountFactory {
+    pub amount: u64,
+}
+
+impl Generator for FixedAmountFactory {
+    fn inscribe_generate(
+        &self,
+        _deploy_args: &Vec<u8>,
+        _seed: &InscribeSeed,
+        _recipient: &Address,
+        _user_input: Option<String>,
+    ) -> GeneratorResult<InscribeGenerateOutput> {
+        Ok(InscribeGenerateOutput {
+            amount: self.amount,
+            attributes: None,
+            content: None,
+        })
+    }
+
+    fn inscribe_verify(
+        &self,
+        deploy_args: &Vec<u8>,
+        seed: &InscribeSeed,
+        recipient: &Address,
+        user_input: Option<String>,
+        inscribe_output: InscribeGenerateOutput,
+    ) -> GeneratorResult<bool> {
+        Ok(self.inscribe_generate(deploy_args, seed, recipient, user_input)? == inscribe_output)
+    }
+}
+
+/// The mint attributes carry a nonce, the sha3 of the seed, the recipient script and
+/// the little endian nonce must have at least `difficulty` leading zero bits.
+pub struct PowFactory {
+    pub amount: u64,
+    pub difficulty: u32,
+}
+
+impl PowFactory {
+    fn work(seed: &InscribeSeed, recipient: &Address, nonce: u64) -> H256 {
+        let mut buffer = seed.seed().as_bytes().to_vec();
+        buffer.extend_from_slice(recipient.script_pubkey().as_bytes());
+        buffer.extend_from_slice(&nonce.to_le_bytes());
+        hash::sha3_256_of(&buffer)
+    }
+
+    fn check_work(&self, seed: &InscribeSeed, recipient: &Address, nonce: u64) -> bool {
+        leading_zero_bits(&Self::work(seed, recipient, nonce)) >= self.difficulty
+    }
+
+    /// Try the nonces below `attempts`, every attempt costs one unit of fuel.
+    fn find_nonce(
+        &self,
+        seed: &InscribeSeed,
+        recipient: &Address,
+        attempts: u64,
+    ) -> GeneratorResult<u64> {
+        (0..attempts)
+            .find(|nonce| self.check_work(seed, recipient, *nonce))
+            .ok_or(GeneratorError::OutOfFuel { limit: attempts })
+    }
+
+    fn output(&self, nonce: u64) -> InscribeGenerateOutput {
+        InscribeGenerateOutput {
+            amount: self.amount,
+            attributes: Some(Value::Map(vec![(
+                Value::Text(NONCE.to_string()),
+                Value::Integer(nonce.into()),
+            )])),
+            content: None,
+        }
+    }
+}
+
+impl Generator for PowFactory {
+    fn inscribe_generate(
+        &self,
+        _deploy_args: &Vec<u8>,
+        seed: &InscribeSeed,
+        recipient: &Address,
+        _user_input: Option<String>,
+    ) -> GeneratorResult<InscribeGenerateOutput> {
+        let nonce = self.find_nonce(seed, recipient, MAX_POW_ATTEMPTS)?;
+        Ok(self.output(nonce))
+    }
+
+    fn inscribe_verify(
+        &self,
+        _deploy_args: &Vec<u8>,
+        seed: &InscribeSeed,
+        recipient: &Address,
+        _user_input: Option<String>,
+        inscribe_output: InscribeGenerateOutput,
+    ) -> GeneratorResult<bool> {
+        let nonce = match inscribe_output
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.as_map())
+            .and_then(|map| match map.as_slice() {
+                [(Value::Text(key), Value::Integer(nonce))] if key == NONCE => {
+                    u64::try_from(*nonce).ok()
+                }
+                _ => None,
+            }) {
+            Some(nonce) => nonce,
+            None => return Ok(false),
+        };
+        Ok(inscribe_output == self.output(nonce) && self.check_work(seed, recipient, nonce))
+    }
+}
+
+fn leading_zero_bits(hash: &H256) -> u32 {
+    let mut bits = 0;
+    for byte in hash.as_bytes() {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::deploy_args_cbor_encode;
+    use bitcoin::hashes::sha256d;
+    use bitcoin::{BlockHash, Network, OutPoint, Txid};
+    use std::str::FromStr;
+
+    fn seed(vout: u32) -> InscribeSeed {
+        let block_hash = BlockHash::from(
+            sha256d::Hash::from_str(
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            )
+            .unwrap(),
+        );
+        let txid = Txid::from(
+            sha256d::Hash::from_str(
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            )
+            .unwrap(),
+        );
+        InscribeSeed::new(block_hash, OutPoint::new(txid, vout))
+    }
+
+    fn recipient() -> Address {
+        Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap()
+    }
+
+    fn deploy_args(args: &[&str]) -> Vec<u8> {
+        deploy_args_cbor_encode(args.iter().map(|arg| arg.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_factories() {
+        let fixed_args = deploy_args(&[r#"{"amount":{"type":"range","data":{"min":7,"max":7}}}"#]);
+        let fixed = load(FIXED, &fixed_args).unwrap();
+        let output = fixed
+            .inscribe_generate(&fixed_args, &seed(0), &recipient(), None)
+            .unwrap();
+        assert_eq!(output.amount, 7);
+
+        let random_args =
+            deploy_args(&[r#"{"amount":{"type":"range","data":{"min":10,"max":20}}}"#]);
+        let random = load(RANDOM, &random_args).unwrap();
+        for vout in 0..16 {
+            let output = random
+                .inscribe_generate(&random_args, &seed(vout), &recipient(), None)
+                .unwrap();
+            assert!(output.amount >= 10 && output.amount <= 20);
+            assert!(random
+                .inscribe_verify(&random_args, &seed(vout), &recipient(), None, output)
+                .unwrap());
+        }
+
+        let pow_args = deploy_args(&[
+            r#"{"amount":{"type":"range","data":{"min":1000,"max":1000}}}"#,
+            r#"{"difficulty":{"type":"range","data":{"min":8,"max":8}}}"#,
+        ]);
+        let pow = load(POW, &pow_args).unwrap();
+        let output = pow
+            .inscribe_generate(&pow_args, &seed(0), &recipient(), None)
+            .unwrap();
+        assert_eq!(output.amount, 1000);
+        assert!(pow
+            .inscribe_verify(&pow_args, &seed(0), &recipient(), None, output.clone())
+            .unwrap());
+        let mut tampered = output;
+        tampered.amount = 1001;
+        assert!(!pow
+            .inscribe_verify(&pow_args, &seed(0), &recipient(), None, tampered)
+            .unwrap());
+
+        // The work is bounded, a nonce which can not be found is an error.
+        let hard = PowFactory {
+            amount: 1000,
+            difficulty: MAX_POW_DIFFICULTY as u32,
+        };
+        assert_eq!(
+            hard.find_nonce(&seed(0), &recipient(), 1),
+            Err(GeneratorError::OutOfFuel { limit: 1 })
+        );
+
+        assert!(load("unknown", &fixed_args).is_err());
+        assert!(load(FIXED, &random_args).is_err());
+        assert!(load(POW, &fixed_args).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::cbor;
+use crate::generator::hash;
+use anyhow::{anyhow, bail, ensure, Result};
+use ciborium::{value::Integer, Value};
+use primitive_types::H256;
+use serde::{Deserialize, Serialize};
+
+/// The ABI version of the host functions and the data exchanged with the WASM generator,
+/// see wasm_generator.rs. Bump it when the host ABI changes.
+/// 1: the input has `attrs`, `seed` and `user_input`.
+/// 2: the input also has `abi_version`, `recipient` and `recipient_script_pubkey`, the attributes
+///    of the output are exchanged in the canonical CBOR form.
+pub const ABI_VERSION: u64 = 2;
+/// The ABI versions the host knows how to call, every generator is called with the input
+/// of the version declared in its metadata.
+pub const SUPPORTED_ABI_VERSIONS: [u64; 2] = [1, 2];
+
+/// The first ABI version which exchanges the attributes in the canonical CBOR form.
+const CANONICAL_ATTRIBUTES_ABI_VERSION: u64 = 2;
+
+const NAME: &str = "name";
+const ABI: &str = "abi_version";
+const VERSION: &str = "version";
+const DESCRIPTION: &str = "description";
+const AUTHOR: &str = "author";
+const HASH: &str = "hash";
+
+/// The metadata of a generator inscription, stored in its attributes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratorMetadata {
+    pub name: String,
+    pub abi_version: u64,
+    // The semantic version of the generator, e.g. "1.0.0"
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    // The sha3 hash of the uncompressed bytecode
+    pub hash: Option<H256>,
+}
+
+impl GeneratorMetadata {
+    pub fn new(name: String, bytecode: &[u8]) -> Self {
+        Self {
+            name,
+            abi_version: ABI_VERSION,
+            version: None,
+            description: None,
+            author: None,
+            hash: Some(hash::sha3_256_of(bytecode)),
+        }
+    }
+
+    pub fn to_attributes(&self) -> Value {
+        let mut map = vec![
+            (
+                Value::Text(NAME.to_string()),
+                Value::Text(self.name.clone()),
+            ),
+            (
+                Value::Text(ABI.to_string()),
+                Value::Integer(Integer::from(self.abi_version)),
+            ),
+        ];
+        for (key, value) in [
+            (VERSION, &self.version),
+            (DESCRIPTION, &self.description),
+            (AUTHOR, &self.author),
+        ] {
+            if let Some(value) = value {
+                map.push((Value::Text(key.to_string()), Value::Text(value.clone())));
+            }
+        }
+        if let Some(hash) = &self.hash {
+            map.push((
+                Value::Text(HASH.to_string()),
+                Value::Bytes(hash.as_bytes().to_vec()),
+            ));
+        }
+        Value::Map(map)
+    }
+
+    /// Parse the metadata from the generator attributes. The generators inscribed before the
+    /// metadata was introduced only have a name, they use the first ABI version.
+    pub fn from_attributes(attributes: &Value) -> Result<Self> {
+        let map = attributes
+            .as_map()
+            .ok_or_else(|| anyhow!("The generator attributes are not a map"))?;
+        let get = |key: &str| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v)
+        };
+        let get_text = |key: &str| -> Result<Option<String>> {
+            get(key)
+                .map(|value| {
+                    value
+                        .as_text()
+                        .map(|text| text.to_string())
+                        .ok_or_else(|| anyhow!("The generator {} is not a text", key))
+                })
+                .transpose()
+        };
+
+        let name = get_text(NAME)?.ok_or_else(|| anyhow!("The generator has no name"))?;
+        let abi_version = match get(ABI) {
+            Some(value) => value
+                .as_integer()
+                .and_then(|abi_version| u64::try_from(abi_version).ok())
+                .ok_or_else(|| anyhow!("The generator abi_version is not an integer"))?,
+            None => 1,
+        };
+        let version = get_text(VERSION)?;
+        if let Some(version) = &version {
+            check_version(version)?;
+        }
+        let hash = match get(HASH) {
+            Some(value) => {
+                let bytes = value
+                    .as_bytes()
+                    .filter(|bytes| bytes.len() == hash::LENGTH)
+                    .ok_or_else(|| anyhow!("The generator hash is not 32 bytes"))?;
+                Some(H256::from_slice(bytes))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name,
+            abi_version,
+            version,
+            description: get_text(DESCRIPTION)?,
+            author: get_text(AUTHOR)?,
+            hash,
+        })
+    }
+
+    /// Check that the host can call the generator and the bytecode matches the hash.
+    pub fn check(&self, bytecode: &[u8]) -> Result<()> {
+        ensure!(
+            SUPPORTED_ABI_VERSIONS.contains(&self.abi_version),
+            "The generator {} uses the ABI version {}, the supported versions are {:?}",
+            self.name,
+            self.abi_version,
+            SUPPORTED_ABI_VERSIONS
+        );
+        if let Some(hash) = &self.hash {
+            ensure!(
+                *hash == hash::sha3_256_of(bytecode),
+                "The bytecode of the generator {} does not match its hash",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The attributes of the mint output in the form the generator of `abi_version` encodes them.
+/// The older generators compare the encoded outputs in their own key order, which is the order
+/// of the inscribed attributes, so they are kept as they are.
+pub fn abi_attributes(abi_version: u64, attributes: Option<Value>) -> Option<Value> {
+    if abi_version >= CANONICAL_ATTRIBUTES_ABI_VERSION {
+        attributes.map(cbor::canonicalize)
+    } else {
+        attributes
+    }
+}
+
+/// Check the version is a semantic version MAJOR.MINOR.PATCH, with an optional pre-release or build suffix.
+fn check_version(version: &str) -> Result<()> {
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.len() != 3
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+    {
+        bail!(
+            "The generator version {:?} is not a semantic version",
+            version
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generator_metadata() {
+        let bytecode = b"\0asm";
+        let mut metadata = GeneratorMetadata::new("random".to_string(), bytecode);
+        metadata.version = Some("1.2.3-beta".to_string());
+        metadata.author = Some("bitseed".to_string());
+
+        let decoded = GeneratorMetadata::from_attributes(&metadata.to_attributes()).unwrap();
+        assert_eq!(decoded, metadata);
+        decoded.check(bytecode).unwrap();
+        assert!(decoded.check(b"other").is_err());
+
+        metadata.abi_version = ABI_VERSION + 1;
+        let decoded = GeneratorMetadata::from_attributes(&metadata.to_attributes()).unwrap();
+        assert!(decoded.check(bytecode).is_err());
+
+        let legacy = Value::Map(vec![(
+            Value::Text("name".to_string()),
+            Value::Text("random".to_string()),
+        )]);
+        let decoded = GeneratorMetadata::from_attributes(&legacy).unwrap();
+        assert_eq!(decoded.abi_version, 1);
+        decoded.check(bytecode).unwrap();
+
+        metadata.version = Some("1.2".to_string());
+        assert!(GeneratorMetadata::from_attributes(&metadata.to_attributes()).is_err());
+    }
+}
@@ -1,8 +1,29 @@
+use crate::error::GeneratorResult;
 use crate::generator::{Generator, InscribeSeed};
 use bitcoin::Address;
 use primitive_types::U256;
 
-pub struct RandomAmountGenerator;
+/// Mint a uniform random amount in [min, max] from the inscribe seed.
+pub struct RandomAmountGenerator {
+    min: u64,
+    max: u64,
+}
+
+impl RandomAmountGenerator {
+    pub fn new(min: u64, max: u64) -> Self {
+        assert!(
+            min <= max,
+            "the min amount must not be greater than the max"
+        );
+        Self { min, max }
+    }
+}
+
+impl Default for RandomAmountGenerator {
+    fn default() -> Self {
+        Self::new(1, 99)
+    }
+}
 
 impl Generator for RandomAmountGenerator {
     fn inscribe_generate(
@@ -11,16 +32,15 @@ impl Generator for RandomAmountGenerator {
         seed: &InscribeSeed,
         _recipient: &Address,
         _user_input: Option<String>,
-    ) -> crate::generator::InscribeGenerateOutput {
+    ) -> GeneratorResult<crate::generator::InscribeGenerateOutput> {
         let hash = seed.seed();
-        let min = 1;
-        let max = 100;
-        let amount = (U256::from_little_endian(hash.as_bytes()) % (max - min) + min).as_u64();
-        crate::generator::InscribeGenerateOutput {
+        let range = U256::from(self.max) - U256::from(self.min) + 1;
+        let amount = (U256::from_little_endian(hash.as_bytes()) % range + self.min).as_u64();
+        Ok(crate::generator::InscribeGenerateOutput {
             amount,
             attributes: None,
             content: None,
-        }
+        })
     }
 
     fn inscribe_verify(
@@ -30,8 +50,8 @@ impl Generator for RandomAmountGenerator {
         recipient: &Address,
         user_input: Option<String>,
         inscribe_output: crate::generator::InscribeGenerateOutput,
-    ) -> bool {
-        let output = self.inscribe_generate(deploy_args, seed, recipient, user_input);
-        output == inscribe_output
+    ) -> GeneratorResult<bool> {
+        let output = self.inscribe_generate(deploy_args, seed, recipient, user_input)?;
+        Ok(output == inscribe_output)
     }
 }
@@ -1,5 +1,9 @@
+use crate::error::{self, GeneratorResult, InvalidOperation};
+use crate::generator::metadata::{GeneratorMetadata, ABI_VERSION};
 use crate::generator::wasm::wasm_generator::WASMGenerator;
-use crate::{sft::Content, wallet::Wallet, GENERATOR_TICK};
+use crate::{
+    operation::DeployRecord, operation::Operation, sft::Content, wallet::Wallet, GENERATOR_TICK,
+};
 use anyhow::{anyhow, bail, ensure, Result};
 use bitcoin::{hashes::Hash, Address, BlockHash};
 use ord::InscriptionId;
@@ -7,7 +11,9 @@ use primitive_types::H256;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
+pub mod factory;
 pub(crate) mod hash;
+pub mod metadata;
 pub(crate) mod mock;
 pub mod wasm;
 
@@ -71,7 +77,10 @@ impl IndexerSeed {
 
 pub const TICK: &'static str = "generator";
 pub const CONTENT_TYPE: &'static str = "application/wasm";
+/// The limit of the decompressed generator bytecode.
+pub const MAX_BYTECODE_SIZE: u64 = 16 * 1024 * 1024;
 
+/// The generator runs untrusted code, so the calls are fallible, a failed call makes the mint invalid.
 pub trait Generator {
     fn inscribe_generate(
         &self,
@@ -79,7 +88,7 @@ pub trait Generator {
         seed: &InscribeSeed,
         recipient: &Address,
         user_input: Option<String>,
-    ) -> InscribeGenerateOutput;
+    ) -> GeneratorResult<InscribeGenerateOutput>;
 
     fn inscribe_verify(
         &self,
@@ -88,10 +97,15 @@ pub trait Generator {
         recipient: &Address,
         user_input: Option<String>,
         inscribe_output: InscribeGenerateOutput,
-    ) -> bool;
+    ) -> GeneratorResult<bool>;
 
-    fn has_indexer_generate(&self) -> bool {
-        false
+    /// The ABI version the generator is called with, see metadata.rs.
+    fn abi_version(&self) -> u64 {
+        ABI_VERSION
+    }
+
+    fn has_indexer_generate(&self) -> GeneratorResult<bool> {
+        Ok(false)
     }
 
     fn indexer_generate(
@@ -99,8 +113,8 @@ pub trait Generator {
         _deploy_args: Vec<u8>,
         _seed: &IndexerSeed,
         _recipient: Address,
-    ) -> IndexerGenerateOutput {
-        IndexerGenerateOutput::default()
+    ) -> GeneratorResult<IndexerGenerateOutput> {
+        Ok(IndexerGenerateOutput::default())
     }
 }
 
@@ -128,8 +142,8 @@ impl Generator for StaticGenerator {
         _seed: &InscribeSeed,
         _recipient: &Address,
         _user_input: Option<String>,
-    ) -> InscribeGenerateOutput {
-        self.inscribe_output.clone()
+    ) -> GeneratorResult<InscribeGenerateOutput> {
+        Ok(self.inscribe_output.clone())
     }
 
     fn inscribe_verify(
@@ -139,12 +153,12 @@ impl Generator for StaticGenerator {
         _recipient: &Address,
         _user_input: Option<String>,
         inscribe_output: InscribeGenerateOutput,
-    ) -> bool {
-        self.inscribe_output == inscribe_output
+    ) -> GeneratorResult<bool> {
+        Ok(self.inscribe_output == inscribe_output)
     }
 
-    fn has_indexer_generate(&self) -> bool {
-        self.indexer_output.is_some()
+    fn has_indexer_generate(&self) -> GeneratorResult<bool> {
+        Ok(self.indexer_output.is_some())
     }
 
     fn indexer_generate(
@@ -152,8 +166,8 @@ impl Generator for StaticGenerator {
         _deploy_args: Vec<u8>,
         _seed: &IndexerSeed,
         _recipient: Address,
-    ) -> IndexerGenerateOutput {
-        self.indexer_output.clone().unwrap()
+    ) -> GeneratorResult<IndexerGenerateOutput> {
+        Ok(self.indexer_output.clone().unwrap_or_default())
     }
 }
 
@@ -165,16 +179,76 @@ impl GeneratorLoader {
     pub fn new(wallet: Wallet) -> Self {
         Self { wallet }
     }
-    pub fn load(&self, generator: &str) -> Result<Box<dyn Generator>> {
-        // generator: "/inscription/inscriptioin_id"
+
+    /// Load the generator inscription or the built-in factory of the deploy. A generator which
+    /// can not be loaded makes the deploy invalid, unless bitcoind or the store fails. The
+    /// generator inscription must be confirmed at or before the block `height` if it is given.
+    pub fn load_deploy(
+        &self,
+        deploy: &DeployRecord,
+        height: Option<u64>,
+    ) -> Result<Box<dyn Generator>> {
+        match (&deploy.generator, &deploy.factory) {
+            (Some(generator), None) => self.load(generator, height),
+            (None, Some(factory)) => {
+                factory::load(factory, &deploy.deploy_args).map_err(error::invalid)
+            }
+            _ => Err(InvalidOperation::new(format!(
+                "The deploy of tick {} must have either a generator or a factory",
+                deploy.tick
+            ))
+            .into()),
+        }
+    }
+
+    fn wasm_generator(&self, metadata: &GeneratorMetadata, bytecode: Vec<u8>) -> WASMGenerator {
+        WASMGenerator::new(bytecode)
+            .with_abi_version(metadata.abi_version)
+            .with_cache(self.wallet.module_cache().clone())
+    }
+
+    /// Load and compile the generator. The stored generators are cached by the inscription id,
+    /// so the confirmation is checked before the store.
+    pub fn load(&self, generator: &str, height: Option<u64>) -> Result<Box<dyn Generator>> {
+        let inscription_id = Self::inscription_id(generator).map_err(error::invalid)?;
+        if let Some(height) = height {
+            self.wallet.check_confirmed(inscription_id, height)?;
+        }
+
+        let store = self.wallet.store()?;
+        // The ABI version is checked when the deploy is indexed, so a deploy with an unsupported
+        // generator does not take the tick. The stored generator is checked again, the supported
+        // ABI versions may be changed.
+        let (metadata, wasm_bytecode) = match store.get_generator(&inscription_id)? {
+            Some((metadata, wasm_bytecode)) => {
+                metadata.check(&wasm_bytecode).map_err(error::invalid)?;
+                (metadata, wasm_bytecode)
+            }
+            None => {
+                let operation = self.wallet.get_operation_at(inscription_id, height)?;
+                let (metadata, wasm_bytecode) =
+                    Self::bytecode(&operation).map_err(error::invalid)?;
+                store.insert_generator(&inscription_id, &metadata, &wasm_bytecode)?;
+                (metadata, wasm_bytecode)
+            }
+        };
+
+        let generator = self.wasm_generator(&metadata, wasm_bytecode);
+        generator.compile()?;
+        Ok(Box::new(generator))
+    }
+
+    /// The inscription id of the generator path "/inscription/<inscription_id>".
+    fn inscription_id(generator: &str) -> Result<InscriptionId> {
         let path = generator.split('/').collect::<Vec<&str>>();
         if path.len() != 3 {
             bail!("Invalid generator path: {:?}", generator);
         }
-        let inscription_id = InscriptionId::from_str(path[2])?;
-        let operation = self
-            .wallet
-            .get_operation_by_inscription_id(inscription_id)?;
+        Ok(InscriptionId::from_str(path[2])?)
+    }
+
+    /// The metadata and the checked bytecode of the generator mint.
+    fn bytecode(operation: &Operation) -> Result<(GeneratorMetadata, Vec<u8>)> {
         let mint_record = operation
             .as_mint()
             .ok_or_else(|| anyhow!("Operation is not mint: {:?}", operation))?;
@@ -191,7 +265,15 @@ impl GeneratorLoader {
             "Invalid generator content type: {:?}",
             content.content_type
         );
-        let wasm_bytecode = &content.body;
-        Ok(Box::new(WASMGenerator::new(wasm_bytecode.clone())))
+        let wasm_bytecode = content.decoded_body(MAX_BYTECODE_SIZE)?;
+        let metadata = GeneratorMetadata::from_attributes(
+            mint_record
+                .sft
+                .attributes
+                .as_ref()
+                .ok_or_else(|| anyhow!("No attributes in generator mint record"))?,
+        )?;
+        metadata.check(&wasm_bytecode)?;
+        Ok((metadata, wasm_bytecode))
     }
 }
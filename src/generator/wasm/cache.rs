@@ -0,0 +1,216 @@
+use crate::error::{GeneratorError, GeneratorResult};
+use crate::generator::wasm::limits::ExecutionLimits;
+use primitive_types::H256;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+use wasmer::{Engine, Module};
+
+/// Compile the generator bytecode with an engine which enforces the limits.
+/// The fuel metering can only be attached to one module, so every module has its own engine.
+pub fn compile(bytecode: &[u8], limits: &ExecutionLimits) -> GeneratorResult<(Engine, Module)> {
+    let engine = limits.engine();
+    let module =
+        Module::new(&engine, bytecode).map_err(|e| GeneratorError::Instantiation(e.to_string()))?;
+    Ok((engine, module))
+}
+
+/// The maximum number of the compiled modules kept in memory.
+pub const MAX_CACHED_MODULES: usize = 64;
+
+/// The modules in memory, the oldest one is dropped when the cache is full.
+#[derive(Default)]
+struct Modules {
+    modules: HashMap<String, (Engine, Module)>,
+    order: VecDeque<String>,
+}
+
+impl Modules {
+    fn get(&self, key: &str) -> Option<(Engine, Module)> {
+        self.modules.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: String, module: (Engine, Module)) {
+        if self.modules.insert(key.clone(), module).is_some() {
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > MAX_CACHED_MODULES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.modules.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// ModuleCache keeps the compiled generator modules, keyed by the sha3 hash of the bytecode,
+/// in memory and serialized in `dir`, so a generator is not compiled again for every mint.
+/// The clones share the modules in memory.
+#[derive(Clone)]
+pub struct ModuleCache {
+    dir: Option<PathBuf>,
+    modules: Arc<Mutex<Modules>>,
+}
+
+impl ModuleCache {
+    /// A cache which only keeps the modules in memory if `dir` is None.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            modules: Arc::new(Mutex::new(Modules::default())),
+        }
+    }
+
+    pub fn load(
+        &self,
+        hash: &H256,
+        bytecode: &[u8],
+        limits: &ExecutionLimits,
+    ) -> GeneratorResult<(Engine, Module)> {
+        let key = Self::key(hash, limits);
+        if let Some(cached) = self
+            .modules
+            .lock()
+            .expect("getting modules mutex failed")
+            .get(&key)
+        {
+            return Ok(cached);
+        }
+
+        let path = self
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.bin", key)));
+        let cached = match path.as_deref().and_then(|path| Self::read(path, limits)) {
+            Some(cached) => cached,
+            None => {
+                let compiled = compile(bytecode, limits)?;
+                if let Some(path) = &path {
+                    if let Err(e) = Self::write(path, &compiled.1) {
+                        warn!("failed to write the module cache {}: {}", path.display(), e);
+                    }
+                }
+                compiled
+            }
+        };
+
+        self.modules
+            .lock()
+            .expect("getting modules mutex failed")
+            .insert(key, cached.clone());
+        Ok(cached)
+    }
+
+    /// The compiled code depends on the wasmer version and the limits, which are
+    /// compiled into the module, so they are part of the key.
+    fn key(hash: &H256, limits: &ExecutionLimits) -> String {
+        format!(
+            "{:x}-wasmer-{}-fuel-{}-pages-{}",
+            hash,
+            wasmer::VERSION,
+            limits.fuel,
+            limits.memory_pages
+        )
+    }
+
+    fn read(path: &Path, limits: &ExecutionLimits) -> Option<(Engine, Module)> {
+        if !path.exists() {
+            return None;
+        }
+        let engine = limits.engine();
+        // Safety: the cache files are only written by `write` from the modules compiled here,
+        // and the artifact header is checked against the engine when it is deserialized.
+        match unsafe { Module::deserialize_from_file(&engine, path) } {
+            Ok(module) => Some((engine, module)),
+            Err(e) => {
+                debug!("ignore the module cache {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn write(path: &Path, module: &Module) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file in the same directory first, so a concurrent reader never
+        // sees a partial module. The name is unique, the processes do not write the same file.
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            uuid::Uuid::new_v4()
+        ));
+        if let Err(e) = module
+            .serialize_to_file(&tmp)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| fs::rename(&tmp, path).map_err(anyhow::Error::from))
+        {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::hash;
+
+    #[test]
+    fn test_module_cache() {
+        let bytecode = wasmer::wat2wasm(br#"(module (memory (export "memory") 1))"#)
+            .unwrap()
+            .to_vec();
+        let hash = hash::sha3_256_of(&bytecode);
+        let limits = ExecutionLimits::default();
+        let dir = std::env::temp_dir().join(format!("bitseed-modules-{}", uuid::Uuid::new_v4()));
+
+        let cache = ModuleCache::new(Some(dir.clone()));
+        cache.load(&hash, &bytecode, &limits).unwrap();
+        assert!(dir
+            .join(format!("{}.bin", ModuleCache::key(&hash, &limits)))
+            .exists());
+
+        // A new cache reads the module from the disk, the bytecode is not compiled again.
+        let (_, module) = ModuleCache::new(Some(dir.clone()))
+            .load(&hash, b"not wasm", &limits)
+            .unwrap();
+        assert!(module.exports().any(|export| export.name() == "memory"));
+
+        // The module compiled with other limits is not reused.
+        let other = ExecutionLimits { fuel: 1, ..limits };
+        assert!(ModuleCache::new(Some(dir.clone()))
+            .load(&hash, b"not wasm", &other)
+            .is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_cache_bound() {
+        let bytecode = wasmer::wat2wasm(br#"(module (memory (export "memory") 1))"#)
+            .unwrap()
+            .to_vec();
+        let limits = ExecutionLimits::default();
+        let cache = ModuleCache::new(None);
+        let mut hashes = Vec::new();
+        // The modules are keyed by the hash, so the same bytecode fills the cache.
+        for i in 0..=MAX_CACHED_MODULES {
+            let hash = H256::from_low_u64_be(i as u64);
+            cache.load(&hash, &bytecode, &limits).unwrap();
+            hashes.push(hash);
+        }
+
+        let modules = cache.modules.lock().unwrap();
+        assert_eq!(modules.modules.len(), MAX_CACHED_MODULES);
+        assert!(modules
+            .get(&ModuleCache::key(&hashes[0], &limits))
+            .is_none());
+        assert!(modules
+            .get(&ModuleCache::key(&hashes[MAX_CACHED_MODULES], &limits))
+            .is_some());
+    }
+}
@@ -0,0 +1,125 @@
+use std::ptr::NonNull;
+use std::sync::Arc;
+use wasmer::vm::{
+    MemoryStyle, TableStyle, VMMemory, VMMemoryDefinition, VMTable, VMTableDefinition,
+};
+use wasmer::{
+    wasmparser::Operator, BaseTunables, CompilerConfig, Cranelift, Engine, EngineBuilder,
+    MemoryError, MemoryType, NativeEngineExt, Pages, TableType, Tunables,
+};
+use wasmer_middlewares::Metering;
+
+/// The fuel of every call of a generator export, every WASM operator costs one unit of fuel.
+pub const FUEL_LIMIT: u64 = 1_000_000_000;
+/// The maximum memory of a generator, 1024 pages of 64 KiB, 64 MiB in total.
+pub const MEMORY_PAGES_LIMIT: u32 = 1024;
+
+/// The limits of executing a generator. They are part of the protocol and can not be configured,
+/// every indexer runs the generators with the default limits to agree on which mints are valid.
+/// The other limits are only used by the tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    pub fuel: u64,
+    pub memory_pages: u32,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self {
+            fuel: FUEL_LIMIT,
+            memory_pages: MEMORY_PAGES_LIMIT,
+        }
+    }
+}
+
+impl ExecutionLimits {
+    /// Build an engine which meters the fuel and caps the memory of the modules it compiles.
+    pub fn engine(&self) -> Engine {
+        let metering = Arc::new(Metering::new(self.fuel, |_: &Operator| 1));
+        let mut compiler = Cranelift::default();
+        compiler.push_middleware(metering);
+
+        let mut engine = Engine::from(EngineBuilder::new(compiler));
+        let base = BaseTunables::for_target(engine.target());
+        engine.set_tunables(LimitingTunables::new(base, Pages(self.memory_pages)));
+        engine
+    }
+}
+
+/// Tunables which cap the maximum memory pages of the module, the `memory.grow` beyond
+/// the limit fails inside the generator instead of allocating on the host.
+struct LimitingTunables<T: Tunables> {
+    limit: Pages,
+    base: T,
+}
+
+impl<T: Tunables> LimitingTunables<T> {
+    fn new(base: T, limit: Pages) -> Self {
+        Self { limit, base }
+    }
+
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = *requested;
+        adjusted.maximum = Some(
+            requested
+                .maximum
+                .map_or(self.limit, |maximum| maximum.min(self.limit)),
+        );
+        adjusted
+    }
+
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "the minimum memory of {} pages exceeds the limit of {} pages",
+                ty.minimum.0, self.limit.0
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(&self.adjust_memory(memory))
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base
+            .create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
@@ -1 +1,3 @@
+pub mod cache;
+pub mod limits;
 pub mod wasm_generator;
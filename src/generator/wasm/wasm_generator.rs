@@ -1,15 +1,20 @@
 use bitcoin::Address;
 use ciborium::Value;
-use serde_json;
-use serde_json::{Number, Value as JSONValue};
-use std::ops::Deref;
+use primitive_types::H256;
 use std::sync::{Arc, Mutex};
 use wasmer::Value::I32;
 use wasmer::*;
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
 
 use tracing::{debug, error};
 
-use crate::generator::{Generator, InscribeGenerateOutput, InscribeSeed};
+use crate::error::{GeneratorError, GeneratorResult};
+use crate::generator::metadata::ABI_VERSION;
+use crate::generator::wasm::cache::{self, ModuleCache};
+use crate::generator::wasm::limits::ExecutionLimits;
+use crate::generator::{
+    hash, Generator, IndexerGenerateOutput, IndexerSeed, InscribeGenerateOutput, InscribeSeed,
+};
 use crate::sft;
 
 #[allow(dead_code)]
@@ -18,24 +23,49 @@ struct Env {
     memory: Option<Arc<Mutex<Memory>>>,
 }
 
-fn js_log(env: FunctionEnvMut<Env>, ptr: i32, len: i32) {
+/// Read `len` bytes at `offset`, the bounds are checked before allocating the buffer,
+/// so a bad length from the generator can not exhaust the host memory.
+fn read_memory(
+    memory_view: &MemoryView,
+    offset: u32,
+    len: u32,
+) -> Result<Vec<u8>, MemoryAccessError> {
+    if offset as u64 + len as u64 > memory_view.data_size() {
+        return Err(MemoryAccessError::HeapOutOfBounds);
+    }
+    let mut buffer = vec![0u8; len as usize];
+    memory_view.read(offset as u64, &mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u32(memory_view: &MemoryView, offset: u32) -> Result<[u8; 4], MemoryAccessError> {
+    let mut bytes: [u8; 4] = [0; 4];
+    memory_view.read(offset as u64, bytes.as_mut_slice())?;
+    Ok(bytes)
+}
+
+fn js_log(env: FunctionEnvMut<Env>, ptr: i32, len: i32) -> Result<(), MemoryAccessError> {
     if let Some(memory_obj) = env.data().memory.clone() {
         let memory = memory_obj.lock().expect("getting memory mutex failed");
         let store_ref = env.as_store_ref();
         let memory_view = memory.view(&store_ref);
 
-        let mut buffer = vec![0u8; len as usize];
-        memory_view
-            .read(ptr as u64, &mut buffer)
-            .expect("read buffer from memory failed");
+        let buffer = read_memory(&memory_view, ptr as u32, len as u32)?;
 
         let message = String::from_utf8_lossy(&buffer);
         debug!("js_log_output: {}", message);
     }
+    Ok(())
 }
 
-fn fd_write(env: FunctionEnvMut<Env>, _fd: i32, mut iov: i32, iovcnt: i32, pnum: i32) -> i32 {
-    let mut written_bytes = 0;
+fn fd_write(
+    env: FunctionEnvMut<Env>,
+    _fd: i32,
+    mut iov: i32,
+    iovcnt: i32,
+    pnum: i32,
+) -> Result<i32, MemoryAccessError> {
+    let mut written_bytes: i32 = 0;
 
     if let Some(memory_obj) = env.data().memory.clone() {
         debug!(
@@ -47,68 +77,49 @@ fn fd_write(env: FunctionEnvMut<Env>, _fd: i32, mut iov: i32, iovcnt: i32, pnum:
         let store_ref = env.as_store_ref();
         let memory_view = memory.view(&store_ref);
 
-        let mut temp_buffer: [u8; 4] = [0; 4];
-
         for _ in 0..iovcnt {
             let ptr_index = iov;
-            let len_index = iov + 4;
-
-            memory_view
-                .read(ptr_index as u64, temp_buffer.as_mut_slice())
-                .expect("read data from memory view failed");
-            let _ptr = u32::from_le_bytes(temp_buffer);
+            let len_index = iov.wrapping_add(4);
 
-            memory_view
-                .read(len_index as u64, temp_buffer.as_mut_slice())
-                .expect("read data from memory view failed");
-            let len = u32::from_le_bytes(temp_buffer);
+            let _ptr = u32::from_le_bytes(read_u32(&memory_view, ptr_index as u32)?);
+            let len = u32::from_le_bytes(read_u32(&memory_view, len_index as u32)?);
 
             debug!("fd_write: _ptr:{}, len:{}", _ptr, len);
 
-            let mut buffer = vec![0u8; len as usize];
-            memory_view
-                .read(_ptr as u64, &mut buffer)
-                .expect("read buffer from memory failed");
+            let buffer = read_memory(&memory_view, _ptr, len)?;
 
+            use std::io::{self, Write};
+            // The output of the generator is only for debugging, the write errors are ignored.
             match _fd {
                 // stdout
                 1 => {
-                    use std::io::{self, Write};
-                    let stdout = io::stdout();
-                    let mut handle = stdout.lock();
-                    handle.write_all(&buffer).expect("write to stdout failed");
+                    let _ = io::stdout().lock().write_all(&buffer);
                 }
                 // stderr
                 2 => {
-                    use std::io::{self, Write};
-                    let stderr = io::stderr();
-                    let mut handle = stderr.lock();
-                    handle.write_all(&buffer).expect("write to stderr failed");
+                    let _ = io::stderr().lock().write_all(&buffer);
                 }
-                // Handle other file descriptors...
-                _ => unimplemented!(),
+                _ => debug!("fd_write: unsupported fd {}", _fd),
             }
 
-            iov += 8;
-            written_bytes += len as i32;
+            iov = iov.wrapping_add(8);
+            written_bytes = written_bytes.wrapping_add(len as i32);
         }
 
         let ret_index = pnum;
         let ret_index_bytes: [u8; 4] = written_bytes.to_le_bytes();
-        memory_view
-            .write(ret_index as u64, ret_index_bytes.as_slice())
-            .expect("write data to memory failed");
+        memory_view.write(ret_index as u32 as u64, ret_index_bytes.as_slice())?;
     }
 
-    written_bytes
+    Ok(written_bytes)
 }
 
 fn convert_i32_pair_to_i53_checked(lo: i32, hi: i32) -> i32 {
     let p0 = if lo > 0 { 1 } else { 0 };
-    let p1 = (hi + 0x200000) >> 0 < (0x400001 - p0);
+    let p1 = hi.wrapping_add(0x200000) < (0x400001 - p0);
     if p1 {
         let (e0, _) = (hi as u32).overflowing_add_signed(429496729);
-        let (e1, _) = (lo >> 0).overflowing_add_unsigned(e0);
+        let (e1, _) = lo.overflowing_add_unsigned(e0);
         e1
     } else {
         0
@@ -123,7 +134,7 @@ fn fd_seek(
     _whence: i32,
 ) -> i32 {
     let _offset = convert_i32_pair_to_i53_checked(offset_low as i32, offset_high);
-    return 70;
+    70
 }
 
 fn fd_close(_env: FunctionEnvMut<Env>, _fd: i32) -> i32 {
@@ -134,120 +145,177 @@ fn proc_exit(_env: FunctionEnvMut<Env>, code: i32) {
     error!("program exit with {:}", code)
 }
 
-fn put_data_on_stack(
-    memory: &mut Arc<Mutex<Memory>>,
-    stack_alloc_func: &Function,
-    store: &mut Store,
-    data: &[u8],
-) -> i32 {
-    let data_len = data.len() as i32;
-    let result = stack_alloc_func
-        .call(store, vec![I32(data_len + 1)].as_slice())
-        .expect("call stackAlloc failed");
-    let return_value = result
-        .deref()
-        .get(0)
-        .expect("the stackAlloc func does not have return value");
-    let offset = return_value
-        .i32()
-        .expect("the return value of stackAlloc is not i32");
-
-    let bindings = memory.lock().expect("getting memory mutex failed");
-    let memory_view = bindings.view(store);
-    memory_view
-        .write(offset as u64, data)
-        .expect("write memory failed");
-
-    offset
+/// An instance of the generator module with its own store, it is used for a single call.
+struct WASMInstance {
+    instance: Instance,
+    store: Store,
+    memory: Memory,
+    fuel: u64,
 }
 
-fn get_data_from_heap(memory: &mut Arc<Mutex<Memory>>, store: &Store, ptr_offset: i32) -> Vec<u8> {
-    let bindings = memory.lock().expect("getting memory mutex failed");
-    let memory_view = bindings.view(store);
-    let mut length_bytes: [u8; 4] = [0; 4];
-    memory_view
-        .read(ptr_offset as u64, length_bytes.as_mut_slice())
-        .expect("read length_bytes failed");
-    let length = u32::from_be_bytes(length_bytes);
-    let mut data = vec![0; length as usize];
-    memory_view
-        .read((ptr_offset + 4) as u64, &mut data)
-        .expect("read uninit failed");
-    data
-}
+impl WASMInstance {
+    fn new(engine: Engine, module: &Module, fuel: u64) -> GeneratorResult<Self> {
+        let mut store = Store::new(engine);
+
+        let env = FunctionEnv::new(&mut store, Env { memory: None });
+
+        let import_object = imports! {
+            "wasi_snapshot_preview1" => {
+                "fd_write" => Function::new_typed_with_env(&mut store, &env, fd_write),
+                "fd_seek" => Function::new_typed_with_env(&mut store, &env, fd_seek),
+                "fd_close" => Function::new_typed_with_env(&mut store, &env, fd_close),
+                "proc_exit" => Function::new_typed_with_env(&mut store, &env, proc_exit),
+            },
+            "env" => {
+                "js_log" => Function::new_typed_with_env(&mut store, &env, js_log),
+            },
+        };
 
-fn create_wasm_instance(bytecode: &Vec<u8>) -> (Instance, Store) {
-    let mut store = Store::default();
-    let module = Module::new(&store, bytecode).unwrap();
+        let instance = Instance::new(&mut store, module, &import_object)
+            .map_err(|e| GeneratorError::Instantiation(e.to_string()))?;
+
+        let memory = instance
+            .exports
+            .get_memory("memory")
+            .map_err(|_| GeneratorError::MissingExport("memory".to_string()))?
+            .clone();
+        env.as_mut(&mut store).memory = Some(Arc::new(Mutex::new(memory.clone())));
+
+        Ok(Self {
+            instance,
+            store,
+            memory,
+            fuel,
+        })
+    }
 
-    let env = FunctionEnv::new(&mut store, Env { memory: None });
+    /// Call the exported function which returns an i32, a trap after the fuel is used up
+    /// is reported as `OutOfFuel`. Every call starts with the full fuel, so the `stackAlloc`
+    /// calls for the input do not take the fuel of the protocol function.
+    fn call(&mut self, name: &str, args: &[wasmer::Value]) -> GeneratorResult<i32> {
+        let function = self
+            .instance
+            .exports
+            .get_function(name)
+            .map_err(|_| GeneratorError::MissingExport(name.to_string()))?
+            .clone();
+        set_remaining_points(&mut self.store, &self.instance, self.fuel);
+        let result =
+            function.call(&mut self.store, args).map_err(|e| {
+                match get_remaining_points(&mut self.store, &self.instance) {
+                    MeteringPoints::Exhausted => GeneratorError::OutOfFuel { limit: self.fuel },
+                    MeteringPoints::Remaining(_) => GeneratorError::Trap(e.to_string()),
+                }
+            })?;
+        result
+            .first()
+            .and_then(|value| value.i32())
+            .ok_or_else(|| GeneratorError::bad_output(format!("{} does not return an i32", name)))
+    }
 
-    let import_object = imports! {
-        "wasi_snapshot_preview1" => {
-            "fd_write" => Function::new_typed_with_env(&mut store, &env, fd_write),
-            "fd_seek" => Function::new_typed_with_env(&mut store, &env, fd_seek),
-            "fd_close" => Function::new_typed_with_env(&mut store, &env, fd_close),
-            "proc_exit" => Function::new_typed_with_env(&mut store, &env, proc_exit),
-        },
-        "env" => {
-            "js_log" => Function::new_typed_with_env(&mut store, &env, js_log),
-        },
-    };
+    fn put_data_on_stack(&mut self, data: &[u8]) -> GeneratorResult<i32> {
+        let data_len = i32::try_from(data.len() + 1)
+            .map_err(|_| GeneratorError::OutOfBounds(format!("{} bytes", data.len())))?;
+        let offset = self.call("stackAlloc", &[I32(data_len)])?;
 
-    let instance = Instance::new(&mut store, &module, &import_object).unwrap();
+        let memory_view = self.memory.view(&self.store);
+        memory_view
+            .write(offset as u32 as u64, data)
+            .map_err(|e| GeneratorError::OutOfBounds(format!("stackAlloc {}: {}", offset, e)))?;
 
-    let memory = instance.exports.get_memory("memory").unwrap();
-    env.as_mut(&mut store).memory = Some(Arc::new(Mutex::new(memory.clone())));
+        Ok(offset)
+    }
+
+    /// Read the data returned by the generator, it is prefixed with its big endian u32 length.
+    fn get_data_from_heap(&self, ptr_offset: i32) -> GeneratorResult<Vec<u8>> {
+        let memory_view = self.memory.view(&self.store);
+        let out_of_bounds =
+            |e: MemoryAccessError| GeneratorError::OutOfBounds(format!("{}: {}", ptr_offset, e));
+        let ptr_offset = ptr_offset as u32;
+        let length = u32::from_be_bytes(read_u32(&memory_view, ptr_offset).map_err(out_of_bounds)?);
+        read_memory(&memory_view, ptr_offset.wrapping_add(4), length).map_err(out_of_bounds)
+    }
 
-    return (instance, store);
+    /// Call the exported function and decode the CBOR data it returns.
+    fn call_for_data(&mut self, name: &str, args: &[wasmer::Value]) -> GeneratorResult<Value> {
+        let offset = self.call(name, args)?;
+        let data = self.get_data_from_heap(offset)?;
+        ciborium::from_reader(data.as_slice()).map_err(GeneratorError::bad_output)
+    }
 }
 
 #[derive(Clone)]
 pub struct WASMGenerator {
     bytecode: Vec<u8>,
+    hash: H256,
+    // The ABI version declared in the metadata of the generator
+    abi_version: u64,
+    limits: ExecutionLimits,
+    cache: Option<ModuleCache>,
 }
 
 impl WASMGenerator {
     pub fn new(bytecode: Vec<u8>) -> Self {
-        Self { bytecode }
+        Self {
+            hash: hash::sha3_256_of(&bytecode),
+            bytecode,
+            abi_version: ABI_VERSION,
+            limits: ExecutionLimits::default(),
+            cache: None,
+        }
     }
 
-    fn generate_buffer_final_ptr(
-        &self,
-        deploy_args: &Vec<u8>,
-        seed: &InscribeSeed,
-        user_input: Option<String>,
-        memory: &mut Arc<Mutex<Memory>>,
-        stack_alloc_func: &Function,
-        store: &mut Store,
-    ) -> i32 {
-        let mut attrs_buffer_vec = Vec::new();
-        for byte in deploy_args.iter() {
-            attrs_buffer_vec.push(serde_json::Value::Number(Number::from(byte.clone())));
-        }
+    /// Call the generator with the ABI version it declares, instead of the latest one.
+    pub fn with_abi_version(mut self, abi_version: u64) -> Self {
+        self.abi_version = abi_version;
+        self
+    }
 
-        let mut buffer_map = serde_json::Map::new();
-        buffer_map.insert(
-            "attrs".to_string(),
-            serde_json::Value::Array(attrs_buffer_vec),
-        );
+    /// Load the compiled module from the cache instead of compiling the bytecode for every call.
+    pub fn with_cache(mut self, cache: ModuleCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
 
-        let seed = hex::encode(seed.seed());
-        buffer_map.insert("seed".to_string(), serde_json::Value::String(seed));
+    /// The limits are protocol constants, only the tests run the generator with other limits.
+    #[cfg(test)]
+    fn with_limits(mut self, limits: ExecutionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        if let Some(input) = user_input {
-            buffer_map.insert("user_input".to_string(), serde_json::Value::String(input));
+    /// Compile the bytecode, so a generator which can not be compiled is found before it is called.
+    pub fn compile(&self) -> GeneratorResult<()> {
+        self.module().map(|_| ())
+    }
+
+    fn module(&self) -> GeneratorResult<(Engine, Module)> {
+        match &self.cache {
+            Some(cache) => cache.load(&self.hash, &self.bytecode, &self.limits),
+            None => cache::compile(&self.bytecode, &self.limits),
         }
+    }
 
-        let top_buffer_map = JSONValue::Object(buffer_map);
-        let mut top_buffer = Vec::new();
-        ciborium::into_writer(&top_buffer_map, &mut top_buffer).expect("ciborium marshal failed");
+    fn instantiate(&self) -> GeneratorResult<WASMInstance> {
+        let (engine, module) = self.module()?;
+        WASMInstance::new(engine, &module, self.limits.fuel)
+    }
+
+    fn generate_buffer_final_ptr(
+        &self,
+        instance: &mut WASMInstance,
+        deploy_args: &Vec<u8>,
+        seed: H256,
+        recipient: &Address,
+        user_input: Option<String>,
+    ) -> GeneratorResult<i32> {
+        let top_buffer = input_to_cbor(self.abi_version, deploy_args, seed, recipient, user_input);
 
         let mut buffer_final = Vec::new();
-        buffer_final.append(&mut (top_buffer.len() as u32).to_be_bytes().to_vec());
-        buffer_final.append(&mut top_buffer);
+        buffer_final.extend_from_slice(&(top_buffer.len() as u32).to_be_bytes());
+        buffer_final.extend_from_slice(&top_buffer);
 
-        put_data_on_stack(memory, stack_alloc_func, store, buffer_final.as_slice())
+        instance.put_data_on_stack(buffer_final.as_slice())
     }
 }
 
@@ -256,50 +324,33 @@ impl Generator for WASMGenerator {
         &self,
         deploy_args: &Vec<u8>,
         seed: &InscribeSeed,
-        _recipient: &Address,
+        recipient: &Address,
         user_input: Option<String>,
-    ) -> InscribeGenerateOutput {
-        let (instance, mut store) = create_wasm_instance(&self.bytecode);
-        let stack_alloc_func = instance.exports.get_function("stackAlloc").unwrap();
-        let inscribe_generate = instance.exports.get_function("inscribe_generate").unwrap();
-
-        let memory_obj = instance.exports.get_memory("memory").unwrap();
-        let mut memory = Arc::new(Mutex::new(memory_obj.clone()));
-
+    ) -> GeneratorResult<InscribeGenerateOutput> {
+        let mut instance = self.instantiate()?;
         let buffer_final_ptr = self.generate_buffer_final_ptr(
+            &mut instance,
             deploy_args,
-            seed,
+            seed.seed(),
+            recipient,
             user_input,
-            &mut memory,
-            stack_alloc_func,
-            &mut store,
-        );
-
-        let func_args = vec![I32(buffer_final_ptr)];
-
-        let calling_result = inscribe_generate
-            .call(&mut store, func_args.as_slice())
-            .expect("call inscribe_generate failed");
+        )?;
 
-        let return_value = calling_result.deref().get(0).unwrap();
-        let offset = return_value.i32().unwrap();
-
-        let data = get_data_from_heap(&mut memory, &store, offset);
-
-        let return_value: Value =
-            ciborium::from_reader(data.as_slice()).expect("ciborium::from_reader failed");
+        let return_value = instance.call_for_data("inscribe_generate", &[I32(buffer_final_ptr)])?;
 
         let mut inscribe_generate_output = InscribeGenerateOutput::default();
 
         for (k, v) in return_value
             .as_map()
-            .expect("the return value from inscribe_generate is incorrect")
+            .ok_or_else(|| GeneratorError::bad_output("the output is not a map"))?
         {
             if let Some(key) = k.as_text() {
                 match key {
                     "amount" => {
-                        let value = u128::try_from(v.as_integer().unwrap()).unwrap();
-                        inscribe_generate_output.amount = value as u64;
+                        inscribe_generate_output.amount = v
+                            .as_integer()
+                            .and_then(|amount| u64::try_from(amount).ok())
+                            .ok_or_else(|| GeneratorError::bad_output("the amount is not a u64"))?;
                     }
                     "attributes" => {
                         inscribe_generate_output.attributes = Some(v.clone());
@@ -312,49 +363,89 @@ impl Generator for WASMGenerator {
             }
         }
 
-        inscribe_generate_output
+        Ok(inscribe_generate_output)
     }
 
     fn inscribe_verify(
         &self,
         deploy_args: &Vec<u8>,
         seed: &InscribeSeed,
-        _recipient: &Address,
+        recipient: &Address,
         user_input: Option<String>,
         inscribe_output: InscribeGenerateOutput,
-    ) -> bool {
-        let (instance, mut store) = create_wasm_instance(&self.bytecode);
-        let stack_alloc_func = instance.exports.get_function("stackAlloc").unwrap();
-        let inscribe_verify = instance.exports.get_function("inscribe_verify").unwrap();
-
-        let memory_obj = instance.exports.get_memory("memory").unwrap();
-        let mut memory = Arc::new(Mutex::new(memory_obj.clone()));
-
+    ) -> GeneratorResult<bool> {
+        let mut instance = self.instantiate()?;
         let buffer_final_ptr = self.generate_buffer_final_ptr(
+            &mut instance,
             deploy_args,
-            seed,
+            seed.seed(),
+            recipient,
             user_input,
-            &mut memory,
-            stack_alloc_func,
-            &mut store,
-        );
+        )?;
 
         let inscribe_output_bytes = inscribe_output_to_cbor(inscribe_output);
-        let inscribe_output_final_ptr = put_data_on_stack(
-            &mut memory,
-            stack_alloc_func,
-            &mut store,
-            inscribe_output_bytes.as_slice(),
-        );
+        let inscribe_output_final_ptr =
+            instance.put_data_on_stack(inscribe_output_bytes.as_slice())?;
+
+        let verified = instance.call(
+            "inscribe_verify",
+            &[I32(buffer_final_ptr), I32(inscribe_output_final_ptr)],
+        )?;
+        Ok(verified == 1)
+    }
+
+    fn abi_version(&self) -> u64 {
+        self.abi_version
+    }
+
+    /// The generator supports indexer_generate if it exports the function, and the optional
+    /// has_indexer_generate export returns 1.
+    fn has_indexer_generate(&self) -> GeneratorResult<bool> {
+        let (engine, module) = self.module()?;
+        let has_function = |name: &str| {
+            module
+                .exports()
+                .functions()
+                .any(|function| function.name() == name)
+        };
+        if !has_function("indexer_generate") {
+            return Ok(false);
+        }
+        if !has_function("has_indexer_generate") {
+            return Ok(true);
+        }
 
-        let func_args = vec![I32(buffer_final_ptr), I32(inscribe_output_final_ptr)];
+        let mut instance = WASMInstance::new(engine, &module, self.limits.fuel)?;
+        Ok(instance.call("has_indexer_generate", &[])? == 1)
+    }
 
-        let calling_result = inscribe_verify
-            .call(&mut store, func_args.as_slice())
-            .expect("call inscribe_verify failed");
+    fn indexer_generate(
+        &self,
+        deploy_args: Vec<u8>,
+        seed: &IndexerSeed,
+        recipient: Address,
+    ) -> GeneratorResult<IndexerGenerateOutput> {
+        let mut instance = self.instantiate()?;
+        let buffer_final_ptr = self.generate_buffer_final_ptr(
+            &mut instance,
+            &deploy_args,
+            seed.seed(),
+            &recipient,
+            None,
+        )?;
+
+        let return_value = instance.call_for_data("indexer_generate", &[I32(buffer_final_ptr)])?;
+
+        // The output has the same form as the inscribe_generate output, only the attributes are used.
+        let attributes = return_value
+            .as_map()
+            .ok_or_else(|| GeneratorError::bad_output("the output is not a map"))?
+            .iter()
+            .find(|(k, _)| k.as_text() == Some("attributes"))
+            .map(|(_, v)| v.clone())
+            .filter(|v| !v.is_null());
 
-        let return_value = calling_result.deref().get(0).unwrap();
-        return_value.i32().unwrap() == 1
+        Ok(IndexerGenerateOutput { attributes })
     }
 }
 
@@ -394,6 +485,47 @@ fn build_content(v: ciborium::Value) -> Option<sft::Content> {
     None
 }
 
+/// Encode the input of the generator functions for the ABI version of the generator,
+/// the keys of the version 2 are only sent to the generators of version 2 or later.
+fn input_to_cbor(
+    abi_version: u64,
+    deploy_args: &[u8],
+    seed: H256,
+    recipient: &Address,
+    user_input: Option<String>,
+) -> Vec<u8> {
+    let text = |s: &str| Value::Text(s.to_string());
+    let mut map = vec![
+        (
+            text("attrs"),
+            Value::Array(
+                deploy_args
+                    .iter()
+                    .map(|byte| Value::Integer((*byte).into()))
+                    .collect(),
+            ),
+        ),
+        (text("seed"), Value::Text(hex::encode(seed))),
+    ];
+
+    if abi_version >= 2 {
+        map.push((text("abi_version"), Value::Integer(abi_version.into())));
+        map.push((text("recipient"), Value::Text(recipient.to_string())));
+        map.push((
+            text("recipient_script_pubkey"),
+            Value::Bytes(recipient.script_pubkey().into_bytes()),
+        ));
+    }
+
+    if let Some(input) = user_input {
+        map.push((text("user_input"), Value::Text(input)));
+    }
+
+    let mut buffer = Vec::new();
+    ciborium::into_writer(&Value::Map(map), &mut buffer).expect("ciborium marshal failed");
+    buffer
+}
+
 fn inscribe_output_to_cbor(inscribe_output: InscribeGenerateOutput) -> Vec<u8> {
     // Create a map to hold the CBOR representation
     let mut map = Vec::new();
@@ -438,12 +570,14 @@ fn inscribe_output_to_cbor(inscribe_output: InscribeGenerateOutput) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generator::metadata::abi_attributes;
     use crate::generator::Content;
     use crate::operation::deploy_args_cbor_encode;
-    use bitcoin::hashes::sha256d;
+    use bitcoin::hashes::{sha256d, Hash};
     use bitcoin::{Address, Network};
     use bitcoin::{BlockHash, Txid};
     use env_logger;
+    use ord::InscriptionId;
     use std::fs::read;
     use std::str::FromStr;
 
@@ -457,7 +591,7 @@ mod tests {
 
         let deploy_args =
             vec![r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()];
-        let deploy_args = deploy_args_cbor_encode(deploy_args);
+        let deploy_args = deploy_args_cbor_encode(deploy_args).unwrap();
 
         // Block hash
         let block_hash_hex = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
@@ -483,7 +617,9 @@ mod tests {
         // User input
         let user_input = Some("test user input".to_string());
 
-        let output = generator.inscribe_generate(&deploy_args, &seed, &recipient, user_input);
+        let output = generator
+            .inscribe_generate(&deploy_args, &seed, &recipient, user_input)
+            .unwrap();
 
         // Add assertions for output
         assert_eq!(output.amount, 1000);
@@ -512,7 +648,7 @@ mod tests {
 
         let deploy_args =
             vec![r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()];
-        let deploy_args = deploy_args_cbor_encode(deploy_args);
+        let deploy_args = deploy_args_cbor_encode(deploy_args).unwrap();
 
         // Block hash
         let block_hash_hex = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
@@ -539,17 +675,67 @@ mod tests {
         let user_input = Some("test user input".to_string());
 
         // Generate output using inscribe_generate
-        let output =
-            generator.inscribe_generate(&deploy_args, &seed, &recipient, user_input.clone());
+        let output = generator
+            .inscribe_generate(&deploy_args, &seed, &recipient, user_input.clone())
+            .unwrap();
 
         // Verify the generated output using inscribe_verify
-        let is_valid =
-            generator.inscribe_verify(&deploy_args, &seed, &recipient, user_input, output);
+        let is_valid = generator
+            .inscribe_verify(&deploy_args, &seed, &recipient, user_input, output)
+            .unwrap();
 
         // Add assertion to check if the output is valid
         assert!(is_valid, "The inscribe output should be valid");
     }
 
+    #[test]
+    fn test_inscribe_verify_non_canonical_attributes() {
+        // The cpp generator outputs the attributes in its own key order, "height" before "id",
+        // and compares the encoded outputs. It is deployed before the ABI version 2.
+        let bytecode = read("./generator/cpp/generator.wasm").expect("failed to read WASM file");
+        let generator = WASMGenerator::new(bytecode).with_abi_version(1);
+
+        let deploy_args =
+            vec![r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()];
+        let deploy_args = deploy_args_cbor_encode(deploy_args).unwrap();
+        let seed = InscribeSeed {
+            block_hash: BlockHash::all_zeros(),
+            utxo: bitcoin::OutPoint::new(Txid::all_zeros(), 0),
+        };
+        let recipient: Address = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+        let user_input = Some("test user input".to_string());
+
+        let output = generator
+            .inscribe_generate(&deploy_args, &seed, &recipient, user_input.clone())
+            .unwrap();
+        let attributes = output.attributes.clone().unwrap();
+        let keys: Vec<&str> = attributes
+            .as_map()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, _)| key.as_text())
+            .collect();
+        assert_eq!(keys, vec!["height", "id"]);
+
+        let verify = |attributes: Option<Value>| {
+            let output = InscribeGenerateOutput {
+                attributes,
+                ..output.clone()
+            };
+            generator
+                .inscribe_verify(&deploy_args, &seed, &recipient, user_input.clone(), output)
+                .unwrap()
+        };
+        assert!(verify(abi_attributes(
+            generator.abi_version(),
+            Some(attributes.clone())
+        )));
+        assert!(!verify(abi_attributes(ABI_VERSION, Some(attributes))));
+    }
+
     #[test]
     fn test_inscribe_verify_for_rust() {
         // Read WASM binary from file
@@ -559,7 +745,7 @@ mod tests {
 
         let deploy_args =
             vec![r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()];
-        let deploy_args = deploy_args_cbor_encode(deploy_args);
+        let deploy_args = deploy_args_cbor_encode(deploy_args).unwrap();
 
         // Block hash
         let block_hash_hex = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
@@ -586,17 +772,215 @@ mod tests {
         let user_input = Some("test user input".to_string());
 
         // Generate output using inscribe_generate
-        let output =
-            generator.inscribe_generate(&deploy_args, &seed, &recipient, user_input.clone());
+        let output = generator
+            .inscribe_generate(&deploy_args, &seed, &recipient, user_input.clone())
+            .unwrap();
 
         // Verify the generated output using inscribe_verify
-        let is_valid =
-            generator.inscribe_verify(&deploy_args, &seed, &recipient, user_input, output);
+        let is_valid = generator
+            .inscribe_verify(&deploy_args, &seed, &recipient, user_input, output)
+            .unwrap();
 
         // Add assertion to check if the output is valid
         assert!(is_valid, "The inscribe output should be valid");
     }
 
+    #[test]
+    fn test_indexer_generate_for_rust() {
+        let bytecode =
+            read("./generator/rust/pkg/generator_bg.wasm").expect("failed to read WASM file");
+        let generator = WASMGenerator::new(bytecode);
+        assert!(generator.has_indexer_generate().unwrap());
+
+        let deploy_args =
+            vec![r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()];
+        let deploy_args = deploy_args_cbor_encode(deploy_args).unwrap();
+
+        let block_hash = BlockHash::from(
+            sha256d::Hash::from_str(
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            )
+            .unwrap(),
+        );
+        let txid = Txid::from(
+            sha256d::Hash::from_str(
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            )
+            .unwrap(),
+        );
+        let seed = IndexerSeed::new(block_hash, InscriptionId { txid, index: 0 });
+        let recipient: Address = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+
+        let output = generator
+            .indexer_generate(deploy_args, &seed, recipient)
+            .unwrap();
+        let attributes = output.attributes.unwrap();
+        assert!(attributes
+            .as_map()
+            .unwrap()
+            .iter()
+            .any(|(key, _)| key.as_text() == Some("height")));
+
+        let without_indexer_generate = WASMGenerator::new(
+            wat2wasm(br#"(module (memory (export "memory") 1))"#)
+                .unwrap()
+                .to_vec(),
+        );
+        assert!(!without_indexer_generate.has_indexer_generate().unwrap());
+    }
+
+    fn generate_wat(
+        inscribe_generate: &str,
+        limits: ExecutionLimits,
+    ) -> GeneratorResult<InscribeGenerateOutput> {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 16) "\00\00\00\01\01")
+                (func (export "stackAlloc") (param i32) (result i32) i32.const 1024)
+                (func (export "inscribe_generate") (param i32) (result i32) {}))"#,
+            inscribe_generate
+        );
+        let bytecode = wat2wasm(wat.as_bytes()).unwrap().to_vec();
+        let generator = WASMGenerator::new(bytecode).with_limits(limits);
+
+        let block_hash = BlockHash::from(
+            sha256d::Hash::from_str(
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            )
+            .unwrap(),
+        );
+        let txid = Txid::from(
+            sha256d::Hash::from_str(
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            )
+            .unwrap(),
+        );
+        let seed = InscribeSeed::new(block_hash, bitcoin::OutPoint::new(txid, 0));
+        let recipient: Address = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+
+        generator.inscribe_generate(&vec![], &seed, &recipient, None)
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        let limits = ExecutionLimits {
+            fuel: 10_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_wat("(loop $forever br $forever) i32.const 0", limits),
+            Err(GeneratorError::OutOfFuel { limit: 10_000 })
+        );
+    }
+
+    #[test]
+    fn test_generator_errors() {
+        let limits = ExecutionLimits::default();
+        assert!(matches!(
+            generate_wat("unreachable", limits),
+            Err(GeneratorError::Trap(_))
+        ));
+        // A negative pointer is beyond the end of the memory.
+        assert!(matches!(
+            generate_wat("i32.const -8", limits),
+            Err(GeneratorError::OutOfBounds(_))
+        ));
+        // The data at 16 is the CBOR integer 1, not a map.
+        assert!(matches!(
+            generate_wat("i32.const 16", limits),
+            Err(GeneratorError::BadOutput(_))
+        ));
+        let small_memory = ExecutionLimits {
+            memory_pages: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generate_wat("i32.const 16", small_memory),
+            Err(GeneratorError::Instantiation(_))
+        ));
+
+        let generator = WASMGenerator::new(
+            wat2wasm(br#"(module (memory (export "memory") 1))"#)
+                .unwrap()
+                .to_vec(),
+        );
+        assert!(matches!(
+            generator.instantiate().and_then(|mut instance| instance.put_data_on_stack(b"")),
+            Err(GeneratorError::MissingExport(name)) if name == "stackAlloc"
+        ));
+        assert!(matches!(
+            WASMGenerator::new(b"not wasm".to_vec()).instantiate(),
+            Err(GeneratorError::Instantiation(_))
+        ));
+    }
+
+    #[test]
+    fn test_input_to_cbor() {
+        let recipient: Address = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+        let input = input_to_cbor(
+            ABI_VERSION,
+            &[1, 2],
+            H256::zero(),
+            &recipient,
+            Some("test user input".to_string()),
+        );
+
+        let value: Value = ciborium::from_reader(input.as_slice()).unwrap();
+        let map = value.as_map().unwrap();
+        let get = |key: &str| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            get("attrs"),
+            Value::Array(vec![Value::Integer(1.into()), Value::Integer(2.into())])
+        );
+        assert_eq!(get("seed"), Value::Text("00".repeat(32)));
+        assert_eq!(get("abi_version"), Value::Integer(ABI_VERSION.into()));
+        assert_eq!(
+            get("recipient"),
+            Value::Text("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf".to_string())
+        );
+        assert_eq!(
+            get("recipient_script_pubkey"),
+            Value::Bytes(recipient.script_pubkey().into_bytes())
+        );
+        assert_eq!(
+            get("user_input"),
+            Value::Text("test user input".to_string())
+        );
+    }
+
+    #[test]
+    fn test_input_to_cbor_v1() {
+        let recipient: Address = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+        let input = input_to_cbor(1, &[1, 2], H256::zero(), &recipient, None);
+
+        let value: Value = ciborium::from_reader(input.as_slice()).unwrap();
+        let keys: Vec<_> = value
+            .as_map()
+            .unwrap()
+            .iter()
+            .map(|(k, _)| k.as_text().unwrap().to_string())
+            .collect();
+        assert_eq!(keys, vec!["attrs", "seed"]);
+    }
+
     #[test]
     fn test_inscribe_output_to_cbor() {
         let mut attributes = Vec::new();
@@ -613,6 +997,8 @@ mod tests {
             amount: 1,
             attributes: Some(ciborium::Value::Map(attributes)),
             content: Some(Content {
+                content_encoding: None,
+                delegate: None,
                 content_type: "text/plain".to_string(),
                 body: vec![104, 101, 108, 108, 111, 32, 119, 111, 114, 108, 100, 33],
             }),
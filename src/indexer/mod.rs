@@ -0,0 +1,1129 @@
+use crate::cbor;
+use crate::error::{self, InvalidOperation};
+use crate::generator::IndexerSeed;
+use crate::inscribe::burn_script;
+use crate::inscription;
+use crate::operation::{AsSFT, DeployRecord, MintRecord, Operation};
+use crate::sft::{Content, SFT};
+use crate::tick;
+use crate::wallet::Wallet;
+use crate::GENERATOR_TICK;
+use anyhow::{anyhow, bail, ensure, Result};
+use bitcoin::{Address, Block, BlockHash, OutPoint, Transaction, TxOut, Txid};
+use bitcoincore_rpc::RpcApi;
+use ciborium::Value;
+use clap::Parser;
+use ord::inscriptions::ParsedEnvelope;
+use ord::InscriptionId;
+use ordinals::SatPoint;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{debug, info, warn};
+
+pub mod state;
+pub mod validator;
+
+pub use state::{IndexerState, SFTEntry, TickEntry};
+pub use validator::MintValidator;
+
+#[derive(Debug, Clone, Parser)]
+pub struct IndexerOptions {
+    #[arg(
+        long,
+        default_value = "0",
+        help = "Index the Bitseed operations from block <INDEX_START_HEIGHT>. The first sync scans all the blocks from it, later syncs continue from the last indexed block. Set it to the height of the first Bitseed inscription to sync faster, the operations before it are not indexed."
+    )]
+    pub index_start_height: u64,
+    #[arg(
+        long,
+        default_value = "6",
+        help = "Blocks with <INDEX_CONFIRMATIONS> confirmations are final, the indexer can only roll back the newer blocks on a reorg."
+    )]
+    pub index_confirmations: u64,
+    #[arg(
+        long,
+        help = "Store the Bitseed index at <INDEX_PATH>, defaults to .bitseed/<CHAIN>/index.redb."
+    )]
+    pub index_path: Option<PathBuf>,
+}
+
+/// Indexer scans Bitcoin blocks and builds the Bitseed protocol state.
+pub struct Indexer {
+    wallet: Wallet,
+    state: IndexerState,
+    start_height: u64,
+    confirmations: u64,
+    mint_validator: MintValidator,
+}
+
+impl Indexer {
+    /// Create an indexer which resumes from the state in the wallet store.
+    pub fn new(wallet: Wallet) -> Result<Self> {
+        let state = wallet.store()?.load_state()?;
+        Ok(Self::with_state(wallet, state))
+    }
+
+    /// Catch up the stored index from the last indexed block to the chain tip. The state is only
+    /// loaded if there are new blocks, the callers read the entries from the store after it.
+    pub fn sync(wallet: &Wallet) -> Result<()> {
+        let client = wallet.bitcoin_client()?;
+        if let Some((height, block_hash)) = wallet.store()?.tip()? {
+            if height == client.get_block_count()? && client.get_block_hash(height)? == block_hash {
+                return Ok(());
+            }
+        }
+        Self::new(wallet.clone())?.index_to_tip()
+    }
+
+    pub fn with_state(wallet: Wallet, state: IndexerState) -> Self {
+        Self {
+            start_height: wallet.index_options().index_start_height,
+            confirmations: wallet.index_options().index_confirmations,
+            mint_validator: MintValidator::new(wallet.clone()),
+            wallet,
+            state,
+        }
+    }
+
+    pub fn state(&self) -> &IndexerState {
+        &self.state
+    }
+
+    pub fn into_state(self) -> IndexerState {
+        self.state
+    }
+
+    /// Index the new blocks on the state shared with the readers, so it does not need to be
+    /// cloned for them. The readers wait while the blocks are indexed.
+    pub fn index_to_tip_shared(&mut self, shared: &RwLock<IndexerState>) -> Result<()> {
+        let mut shared = shared
+            .write()
+            .map_err(|_| anyhow!("The shared indexer state lock is poisoned"))?;
+        std::mem::swap(&mut self.state, &mut *shared);
+        let result = self.index_to_tip();
+        std::mem::swap(&mut self.state, &mut *shared);
+        result
+    }
+
+    /// Index all the blocks from the last indexed height to the chain tip,
+    /// each block is saved to the wallet store once it is indexed.
+    pub fn index_to_tip(&mut self) -> Result<()> {
+        self.reload_if_changed()?;
+        self.check_txindex()?;
+
+        let client = self.wallet.bitcoin_client()?;
+        let tip = client.get_block_count()?;
+        self.rollback_reorged_blocks(tip)?;
+
+        let next_height = self
+            .state
+            .height()
+            .map(|height| height + 1)
+            .unwrap_or(self.start_height);
+
+        for height in next_height..=tip {
+            self.index_block(height)?;
+        }
+        Ok(())
+    }
+
+    /// The indexer treats a transaction which bitcoind does not know as an invalid reference,
+    /// that is only true if bitcoind has the synced transaction index.
+    fn check_txindex(&self) -> Result<()> {
+        let index_info: serde_json::Value = self
+            .wallet
+            .bitcoin_client()?
+            .call("getindexinfo", &["txindex".into()])?;
+        ensure!(
+            index_info["txindex"]["synced"].as_bool() == Some(true),
+            "bitcoind must run with -txindex=1 and the txindex must be synced to index bitseed"
+        );
+        Ok(())
+    }
+
+    /// Reload the state if the store is updated by another process, or a save has failed.
+    fn reload_if_changed(&mut self) -> Result<()> {
+        let store = self.wallet.store()?;
+        if store.tip()? != self.state.tip() {
+            debug!("the stored index is changed, reload the indexer state");
+            self.state = store.load_state()?;
+        }
+        Ok(())
+    }
+
+    /// Save the changes of the state in one write, `base` is the tip before the changes.
+    fn save(&mut self, base: Option<(u64, BlockHash)>) -> Result<()> {
+        let changes = self.state.take_changes();
+        self.wallet
+            .store()?
+            .save_changes(base, &self.state, changes)
+    }
+
+    /// Roll back the indexed blocks which are not in the best chain anymore,
+    /// until the common ancestor is found.
+    fn rollback_reorged_blocks(&mut self, tip: u64) -> Result<()> {
+        let client = self.wallet.bitcoin_client()?;
+        let base = self.state.tip();
+        let mut rolled_back = false;
+
+        while let Some(block) = self.state.last_block() {
+            if block.height <= tip && client.get_block_hash(block.height)? == block.block_hash {
+                break;
+            }
+            info!(
+                "rollback reorged block {} {}",
+                block.height, block.block_hash
+            );
+            self.state.rollback_block();
+            rolled_back = true;
+        }
+
+        // All the undo logs are rolled back, the final block must still be in the best chain,
+        // otherwise the state is rebuilt from the start height.
+        if let Some((height, block_hash)) = self.state.final_block() {
+            if rolled_back
+                && self.state.last_block().is_none()
+                && (height > tip || client.get_block_hash(height)? != block_hash)
+            {
+                warn!(
+                    "the chain is reorged deeper than the final block {} {}, reindex from block {}",
+                    height, block_hash, self.start_height
+                );
+                self.state = IndexerState::new();
+                return self.wallet.store()?.reset_index();
+            }
+        }
+
+        if rolled_back {
+            self.save(base)?;
+        }
+        Ok(())
+    }
+
+    pub fn index_block(&mut self, height: u64) -> Result<()> {
+        let client = self.wallet.bitcoin_client()?;
+        let block_hash = client.get_block_hash(height)?;
+        let block: Block = client.get_block(&block_hash)?;
+
+        if let Some(last_block) = self.state.last_block() {
+            ensure!(
+                last_block.height + 1 == height
+                    && last_block.block_hash == block.header.prev_blockhash,
+                "Block {} {} does not follow the last indexed block {} {}",
+                height,
+                block_hash,
+                last_block.height,
+                last_block.block_hash
+            );
+        }
+
+        debug!(
+            "indexing block {} {} with {} transactions",
+            height,
+            block_hash,
+            block.txdata.len()
+        );
+
+        let base = self.state.tip();
+        for tx in block.txdata.iter() {
+            if let Err(e) = self.index_transaction(height, &block_hash, tx) {
+                self.state.abort_block();
+                return Err(e);
+            }
+        }
+
+        self.state
+            .commit_block(height, block_hash, self.confirmations);
+        self.save(base)
+    }
+
+    fn index_transaction(
+        &mut self,
+        height: u64,
+        block_hash: &BlockHash,
+        tx: &Transaction,
+    ) -> Result<()> {
+        let txid = tx.txid();
+        // The parents must be spent by the transaction, they are collected before the transfer
+        // moves them to the outputs.
+        let spent = Self::spent_inscriptions(&self.state, tx);
+        let burned = self.transfer_sfts(tx)?;
+
+        let envelopes = ParsedEnvelope::from_transaction(tx);
+        if envelopes.is_empty() {
+            return Ok(());
+        }
+
+        let positions = Self::envelope_positions(&envelopes);
+        let mut operations = Vec::new();
+
+        for (index, envelope) in envelopes.into_iter().enumerate() {
+            let inscription_id = InscriptionId {
+                txid,
+                index: index as u32,
+            };
+            let parents = inscription::parents(&envelope.payload);
+            let operation = match Operation::from_inscription(envelope.payload) {
+                Ok(operation) => operation,
+                Err(e) if e.is_malformed() => {
+                    info!(
+                        "malformed bitseed inscription {} ({}): {}",
+                        inscription_id,
+                        e.kind(),
+                        e
+                    );
+                    self.state.insert_invalid(inscription_id, e.to_string());
+                    continue;
+                }
+                Err(e) => {
+                    debug!("skip inscription {}: {}", inscription_id, e);
+                    continue;
+                }
+            };
+            operations.push((inscription_id, parents, operation));
+        }
+
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        // Only the values of the inputs before the last reveal input are needed.
+        let input_count = operations
+            .iter()
+            .map(|(inscription_id, _, _)| positions[inscription_id.index as usize].0)
+            .max()
+            .unwrap_or(0);
+        let input_values = self.input_values(tx, input_count)?;
+        let locations = Self::locate_inscriptions(tx, &positions, &input_values);
+        let mut transfers = Vec::new();
+
+        for (inscription_id, parents, operation) in operations {
+            let operation = match self.resolve_content(operation, &burned, height) {
+                Ok(operation) => operation,
+                Err(e) => {
+                    self.record_invalid(inscription_id, e)?;
+                    continue;
+                }
+            };
+            self.state
+                .insert_operation(inscription_id, operation.clone());
+
+            let satpoint =
+                locations[inscription_id.index as usize].map(|(vout, offset)| SatPoint {
+                    outpoint: OutPoint {
+                        txid,
+                        vout: vout as u32,
+                    },
+                    offset,
+                });
+            if operation.is_split() || operation.is_merge() {
+                transfers.push((inscription_id, satpoint, operation));
+                continue;
+            }
+
+            if let Err(e) = self.apply_operation(
+                height,
+                block_hash,
+                tx,
+                &spent,
+                inscription_id,
+                satpoint,
+                &parents,
+                operation,
+            ) {
+                self.record_invalid(inscription_id, e)?;
+            }
+        }
+
+        if !transfers.is_empty() {
+            // The transfers are only checked against the state, so all the errors are invalid.
+            if let Err(e) = self.apply_transfers(tx, &transfers, &burned) {
+                let reason = format!("{:#}", e);
+                for (inscription_id, _, _) in transfers.iter() {
+                    self.record_invalid(*inscription_id, InvalidOperation::new(&reason).into())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the operation as invalid if it breaks a protocol rule. The other errors, e.g. of
+    /// bitcoind or the store, abort the block, so it is indexed again instead of losing the
+    /// operation.
+    fn record_invalid(&mut self, inscription_id: InscriptionId, e: anyhow::Error) -> Result<()> {
+        if !error::is_invalid(&e) {
+            return Err(e.context(format!("failed to index inscription {}", inscription_id)));
+        }
+        let reason = format!("{:#}", e);
+        info!("invalid bitseed inscription {}: {}", inscription_id, reason);
+        self.state.insert_invalid(inscription_id, reason);
+        Ok(())
+    }
+
+    /// Resolve the delegated content. A missing or empty delegate makes the operation invalid.
+    fn resolve_content(
+        &self,
+        operation: Operation,
+        burned: &[SFTEntry],
+        height: u64,
+    ) -> Result<Operation> {
+        let delegate_content = match operation.unresolved_delegate() {
+            Some(delegate) => self.delegate_content(delegate, burned, height)?,
+            None => None,
+        };
+        operation
+            .resolve_content(delegate_content)
+            .map_err(error::invalid)
+    }
+
+    /// The content of the delegate inscription. The delegate is usually an SFT burned by the same
+    /// transaction, otherwise it is an operation of the indexed blocks, or any inscription on chain
+    /// which is confirmed at or before the block `height`.
+    fn delegate_content(
+        &self,
+        delegate: InscriptionId,
+        burned: &[SFTEntry],
+        height: u64,
+    ) -> Result<Option<Content>> {
+        if let Some(entry) = burned.iter().find(|entry| entry.inscription_id == delegate) {
+            return Ok(entry.sft.content.clone());
+        }
+        if let Some(Some(operation)) = self.state.unsaved_operation(&delegate) {
+            return Ok(operation.content().cloned());
+        }
+        self.wallet.get_delegate_content(delegate, Some(height))
+    }
+
+    /// The reveal input and the pointer of each inscription, they decide where the inscription is.
+    fn envelope_positions(envelopes: &[ParsedEnvelope]) -> Vec<(usize, Option<u64>)> {
+        envelopes
+            .iter()
+            .map(|envelope| {
+                (
+                    envelope.input as usize,
+                    inscription::pointer(&envelope.payload),
+                )
+            })
+            .collect()
+    }
+
+    /// Locate the inscriptions as ord does: an inscription is on the first sat of its reveal input,
+    /// unless its pointer points to a sat of the outputs. `input_values` are the values of the inputs
+    /// before the last reveal input. Returns the output and the offset in it for each inscription,
+    /// None if the inscription is spent as fee.
+    fn locate_inscriptions(
+        tx: &Transaction,
+        positions: &[(usize, Option<u64>)],
+        input_values: &[u64],
+    ) -> Vec<Option<(usize, u64)>> {
+        let total_output: u64 = tx.output.iter().map(|txout| txout.value).sum();
+        positions
+            .iter()
+            .map(|(input, pointer)| {
+                let offset = match pointer {
+                    Some(pointer) if *pointer < total_output => *pointer,
+                    _ => input_values.iter().take(*input).sum(),
+                };
+                Self::locate_output(tx, offset)
+            })
+            .collect()
+    }
+
+    fn apply_operation(
+        &mut self,
+        height: u64,
+        block_hash: &BlockHash,
+        tx: &Transaction,
+        spent: &BTreeSet<InscriptionId>,
+        inscription_id: InscriptionId,
+        satpoint: Option<SatPoint>,
+        parents: &[InscriptionId],
+        operation: Operation,
+    ) -> Result<()> {
+        match operation {
+            Operation::Deploy(deploy_record) => {
+                let mint_validator = &mut self.mint_validator;
+                Self::deploy(
+                    &mut self.state,
+                    spent,
+                    parents,
+                    inscription_id,
+                    deploy_record,
+                    satpoint,
+                    |deploy_record| mint_validator.check_deploy(deploy_record, height),
+                )?;
+            }
+            Operation::Mint(mint_record) => {
+                let (satpoint, txout) = Self::inscription_output(tx, inscription_id, satpoint)
+                    .map_err(error::invalid)?;
+                let sft = mint_record.as_sft();
+                let mut indexer_attributes = None;
+                if sft.tick != GENERATOR_TICK {
+                    let deploy_inscription_id = self
+                        .state
+                        .get_tick(&sft.tick)
+                        .map(|entry| entry.deploy_inscription_id);
+                    Self::check_parents(spent, parents, deploy_inscription_id)
+                        .map_err(error::invalid)?;
+                    self.validate_mint(tx, inscription_id, txout, &mint_record)?;
+                    indexer_attributes =
+                        self.indexer_generate(block_hash, inscription_id, txout, &sft.tick)?;
+                    self.state
+                        .add_mint(&sft.tick, sft.amount, sft.attributes_hash());
+                }
+                self.insert_sft(tx, inscription_id, satpoint, sft, indexer_attributes);
+            }
+            _ => bail!("{} must be applied with the burned SFTs", operation.op()),
+        }
+
+        Ok(())
+    }
+
+    /// Register the first valid deploy of the tick. `check_generator` loads the generator of the
+    /// deploy, a deploy whose generator is missing or broken does not take the tick.
+    fn deploy(
+        state: &mut IndexerState,
+        spent: &BTreeSet<InscriptionId>,
+        parents: &[InscriptionId],
+        inscription_id: InscriptionId,
+        deploy_record: DeployRecord,
+        satpoint: Option<SatPoint>,
+        check_generator: impl FnOnce(&DeployRecord) -> Result<()>,
+    ) -> Result<()> {
+        Self::validate_deploy(state, spent, parents, &deploy_record).map_err(error::invalid)?;
+        check_generator(&deploy_record)?;
+        state.insert_tick(inscription_id, deploy_record, satpoint);
+        Ok(())
+    }
+
+    /// The deploy is only checked against the state here, so all the errors make it invalid.
+    fn validate_deploy(
+        state: &IndexerState,
+        spent: &BTreeSet<InscriptionId>,
+        parents: &[InscriptionId],
+        deploy_record: &DeployRecord,
+    ) -> Result<()> {
+        tick::validate_tick(&deploy_record.tick)?;
+        Self::check_parents(spent, parents, deploy_record.generator_inscription_id())?;
+        if let Some(deployed_by) = state.deployed_by(&deploy_record.tick) {
+            bail!(
+                "tick {} is already deployed by {}",
+                deploy_record.tick,
+                deployed_by
+            );
+        }
+        Ok(())
+    }
+
+    /// A deploy may be a child of its generator and a mint may be a child of its deploy,
+    /// the other parents are not allowed. As ord requires, the parent must be spent by the
+    /// reveal transaction, `spent` are the inscriptions on the inputs of the transaction.
+    fn check_parents(
+        spent: &BTreeSet<InscriptionId>,
+        parents: &[InscriptionId],
+        expected: Option<InscriptionId>,
+    ) -> Result<()> {
+        if parents.is_empty() {
+            return Ok(());
+        }
+        ensure!(
+            expected.is_some_and(|expected| parents == [expected]),
+            "The parents {:?} do not match the expected parent {:?}",
+            parents,
+            expected
+        );
+        ensure!(
+            spent.contains(&parents[0]),
+            "The parent {} is not spent by the transaction",
+            parents[0]
+        );
+        Ok(())
+    }
+
+    /// The SFTs and deploys on the inputs of the transaction.
+    fn spent_inscriptions(state: &IndexerState, tx: &Transaction) -> BTreeSet<InscriptionId> {
+        tx.input
+            .iter()
+            .flat_map(|txin| {
+                let outpoint = &txin.previous_output;
+                state
+                    .sfts_at_outpoint(outpoint)
+                    .into_iter()
+                    .map(|entry| entry.inscription_id)
+                    .chain(
+                        state
+                            .ticks_at_outpoint(outpoint)
+                            .into_iter()
+                            .map(|entry| entry.deploy_inscription_id),
+                    )
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Apply the split or merge operations of the transaction, they must consume the burned SFTs.
+    fn apply_transfers(
+        &mut self,
+        tx: &Transaction,
+        transfers: &[(InscriptionId, Option<SatPoint>, Operation)],
+        burned: &[SFTEntry],
+    ) -> Result<()> {
+        let mut splits = Vec::new();
+        let mut merges = Vec::new();
+        let mut satpoints = Vec::new();
+        for (inscription_id, satpoint, operation) in transfers {
+            satpoints.push(Self::inscription_output(tx, *inscription_id, *satpoint)?.0);
+            match operation {
+                Operation::Split(split_record) => splits.push(split_record),
+                Operation::Merge(merge_record) => merges.push(merge_record),
+                _ => bail!("{} is not a split or merge", operation.op()),
+            }
+        }
+
+        let output_sfts: Vec<SFT> = if merges.is_empty() {
+            validator::validate_split(burned, &splits)?;
+            splits.iter().map(|record| record.as_sft()).collect()
+        } else if splits.is_empty() {
+            validator::validate_merge(burned, &merges)?;
+            merges.iter().map(|record| record.as_sft()).collect()
+        } else {
+            bail!("split and merge can not be in the same transaction");
+        };
+
+        // The indexer generated attributes are part of the SFT, they are kept by split and merge.
+        let indexer_attributes = burned
+            .first()
+            .and_then(|entry| entry.indexer_attributes.clone());
+        ensure!(
+            burned.iter().all(|entry| cbor::canonical_eq_opt(
+                &entry.indexer_attributes,
+                &indexer_attributes
+            )),
+            "SFTs have different indexer attributes"
+        );
+
+        let sources: Vec<InscriptionId> = burned
+            .iter()
+            .map(|entry| entry.inscription_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        for (((inscription_id, _, _), satpoint), sft) in
+            transfers.iter().zip(satpoints).zip(output_sfts)
+        {
+            self.state.insert_lineage(*inscription_id, sources.clone());
+            self.insert_sft(
+                tx,
+                *inscription_id,
+                satpoint,
+                sft,
+                indexer_attributes.clone(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn insert_sft(
+        &mut self,
+        tx: &Transaction,
+        inscription_id: InscriptionId,
+        satpoint: SatPoint,
+        sft: SFT,
+        indexer_attributes: Option<Value>,
+    ) {
+        let owner = self.owner_of(&tx.output[satpoint.outpoint.vout as usize]);
+        self.state.insert_sft(SFTEntry {
+            inscription_id,
+            sft,
+            indexer_attributes,
+            satpoint,
+            owner,
+        });
+    }
+
+    /// The output which receives the inscription, an inscription spent as fee is not bound to one.
+    fn inscription_output(
+        tx: &Transaction,
+        inscription_id: InscriptionId,
+        satpoint: Option<SatPoint>,
+    ) -> Result<(SatPoint, &TxOut)> {
+        let satpoint = satpoint
+            .ok_or_else(|| anyhow!("inscription {} is not bound to an output", inscription_id))?;
+        let txout = tx
+            .output
+            .get(satpoint.outpoint.vout as usize)
+            .ok_or_else(|| anyhow!("inscription output {} not found", satpoint.outpoint))?;
+        Ok((satpoint, txout))
+    }
+
+    fn validate_mint(
+        &mut self,
+        tx: &Transaction,
+        inscription_id: InscriptionId,
+        recipient: &TxOut,
+        mint_record: &MintRecord,
+    ) -> Result<()> {
+        let sft = &mint_record.sft;
+        let tick_entry = self
+            .state
+            .get_tick(&sft.tick)
+            .ok_or_else(|| InvalidOperation::new(format!("tick {} is not deployed", sft.tick)))?;
+
+        tick_entry
+            .deploy
+            .check_supply(tick_entry.minted, sft.amount)
+            .map_err(error::invalid)?;
+        tick_entry
+            .deploy
+            .check_repeat(tick_entry.repeated(&sft.attributes_hash()))
+            .map_err(error::invalid)?;
+
+        self.mint_validator.verify_mint(
+            &tick_entry.deploy,
+            tx,
+            inscription_id.index as usize,
+            recipient,
+            mint_record,
+        )
+    }
+
+    /// Run the indexer_generate of the deploy generator for the confirmed mint.
+    fn indexer_generate(
+        &mut self,
+        block_hash: &BlockHash,
+        inscription_id: InscriptionId,
+        recipient: &TxOut,
+        tick: &str,
+    ) -> Result<Option<Value>> {
+        let tick_entry = self
+            .state
+            .get_tick(tick)
+            .ok_or_else(|| anyhow!("tick {} is not deployed", tick))?;
+        let seed = IndexerSeed::new(*block_hash, inscription_id);
+        self.mint_validator
+            .indexer_generate(&tick_entry.deploy, &seed, recipient)
+    }
+
+    /// Move the SFTs and the deploys on the transaction inputs to the outputs by following
+    /// the sats. Returns the SFTs which are sent to the burn output.
+    fn transfer_sfts(&mut self, tx: &Transaction) -> Result<Vec<SFTEntry>> {
+        if tx.is_coin_base()
+            || !tx.input.iter().any(|txin| {
+                self.state.has_sfts_at_outpoint(&txin.previous_output)
+                    || self.state.has_ticks_at_outpoint(&txin.previous_output)
+            })
+        {
+            return Ok(vec![]);
+        }
+
+        let txid = tx.txid();
+        let burn_script = burn_script();
+        let mut burned = Vec::new();
+        let mut input_offset = 0;
+
+        for txin in tx.input.iter() {
+            let entries: Vec<SFTEntry> = self
+                .state
+                .sfts_at_outpoint(&txin.previous_output)
+                .into_iter()
+                .cloned()
+                .collect();
+
+            for entry in entries {
+                let offset = input_offset + entry.satpoint.offset;
+                match Self::locate_output(tx, offset) {
+                    Some((vout, output_offset)) => {
+                        let txout = &tx.output[vout];
+                        if txout.script_pubkey == burn_script {
+                            self.state.remove_sft(&entry.inscription_id);
+                            burned.push(entry);
+                        } else {
+                            let satpoint = SatPoint {
+                                outpoint: OutPoint {
+                                    txid,
+                                    vout: vout as u32,
+                                },
+                                offset: output_offset,
+                            };
+                            let owner = self.owner_of(txout);
+                            self.state.move_sft(&entry.inscription_id, satpoint, owner);
+                        }
+                    }
+                    None => {
+                        // The SFT is spent as fee, it is lost.
+                        self.state.remove_sft(&entry.inscription_id);
+                    }
+                }
+            }
+
+            // The deploys are only moved, so the mints can be their children after a transfer.
+            let deploys: Vec<(String, SatPoint)> = self
+                .state
+                .ticks_at_outpoint(&txin.previous_output)
+                .into_iter()
+                .filter_map(|entry| Some((entry.deploy.tick.clone(), entry.satpoint?)))
+                .collect();
+            for (tick, satpoint) in deploys {
+                let satpoint = Self::locate_output(tx, input_offset + satpoint.offset).map(
+                    |(vout, offset)| SatPoint {
+                        outpoint: OutPoint {
+                            txid,
+                            vout: vout as u32,
+                        },
+                        offset,
+                    },
+                );
+                self.state.move_tick(&tick, satpoint);
+            }
+
+            input_offset +=
+                self.input_value(&txin.previous_output.txid, txin.previous_output.vout)?;
+        }
+
+        Ok(burned)
+    }
+
+    fn locate_output(tx: &Transaction, offset: u64) -> Option<(usize, u64)> {
+        let mut output_start = 0;
+        for (vout, txout) in tx.output.iter().enumerate() {
+            let output_end = output_start + txout.value;
+            if offset < output_end {
+                return Some((vout, offset - output_start));
+            }
+            output_start = output_end;
+        }
+        None
+    }
+
+    /// The values of the first `count` inputs of the transaction.
+    fn input_values(&self, tx: &Transaction, count: usize) -> Result<Vec<u64>> {
+        if tx.is_coin_base() {
+            return Ok(vec![]);
+        }
+        tx.input
+            .iter()
+            .take(count)
+            .map(|txin| self.input_value(&txin.previous_output.txid, txin.previous_output.vout))
+            .collect()
+    }
+
+    fn input_value(&self, txid: &Txid, vout: u32) -> Result<u64> {
+        let prev_tx = self.wallet.get_raw_transaction(txid)?;
+        match prev_tx.output.get(vout as usize) {
+            Some(txout) => Ok(txout.value),
+            None => bail!("output {}:{} not found", txid, vout),
+        }
+    }
+
+    fn owner_of(&self, txout: &TxOut) -> Option<String> {
+        Address::from_script(&txout.script_pubkey, self.wallet.chain().network())
+            .ok()
+            .map(|address| address.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GeneratorError;
+    use crate::generator::metadata::{GeneratorMetadata, ABI_VERSION};
+    use crate::inscription::{inscription_id_to_bytes, pointer_to_bytes};
+    use crate::operation::DeployRecord;
+    use bitcoin::{hashes::Hash, ScriptBuf, TxIn, Witness};
+    use ord::Inscription;
+
+    fn mint_inscription(pointer: Option<u64>) -> Inscription {
+        let mut inscription = Operation::Mint(MintRecord {
+            sft: SFT::new("BITS".to_string(), 1000, None, None),
+            user_input: None,
+        })
+        .to_inscription();
+        inscription.pointer = pointer.map(pointer_to_bytes);
+        inscription
+    }
+
+    fn txin(vout: u32, inscriptions: &[Inscription]) -> TxIn {
+        let mut witness = Witness::new();
+        if !inscriptions.is_empty() {
+            let script = inscriptions
+                .iter()
+                .fold(ScriptBuf::builder(), |builder, inscription| {
+                    inscription.append_reveal_script_to_builder(builder)
+                })
+                .into_script();
+            witness.push([0u8; 64]);
+            witness.push(script);
+            witness.push([0xc0u8; 33]);
+        }
+        TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout,
+            },
+            witness,
+            ..Default::default()
+        }
+    }
+
+    fn tx(input: Vec<TxIn>, output_values: &[u64]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input,
+            output: output_values
+                .iter()
+                .map(|value| TxOut {
+                    value: *value,
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn locate(tx: &Transaction, input_values: &[u64]) -> Vec<Option<(usize, u64)>> {
+        let envelopes = ParsedEnvelope::from_transaction(tx);
+        let positions = Indexer::envelope_positions(&envelopes);
+        Indexer::locate_inscriptions(tx, &positions, input_values)
+    }
+
+    // The reveal transaction of the Inscriber: the burned SFT and the parent inputs come first,
+    // then one commit input per inscription, and the outputs are in the same order.
+    fn reveal_tx(pointers: [Option<u64>; 2]) -> Transaction {
+        tx(
+            vec![
+                txin(0, &[]),
+                txin(1, &[]),
+                txin(2, &[mint_inscription(pointers[0])]),
+                txin(3, &[mint_inscription(pointers[1])]),
+            ],
+            &[10_000, 546, 10_000, 10_000],
+        )
+    }
+
+    const INPUT_VALUES: [u64; 4] = [10_000, 546, 330, 25_000];
+
+    #[test]
+    fn test_locate_inscriptions_with_pointers() {
+        let tx = reveal_tx([Some(10_546), Some(20_546)]);
+        assert_eq!(locate(&tx, &INPUT_VALUES), vec![Some((2, 0)), Some((3, 0))]);
+    }
+
+    #[test]
+    fn test_locate_inscriptions_by_sats() {
+        // Without pointers the inscriptions follow the sats of the dust commit inputs,
+        // so they are on the same output.
+        let tx = reveal_tx([None, None]);
+        assert_eq!(
+            locate(&tx, &INPUT_VALUES),
+            vec![Some((2, 0)), Some((2, 330))]
+        );
+
+        // A pointer out of the outputs is ignored.
+        let tx = reveal_tx([Some(30_546), Some(10)]);
+        assert_eq!(
+            locate(&tx, &INPUT_VALUES),
+            vec![Some((2, 0)), Some((0, 10))]
+        );
+    }
+
+    #[test]
+    fn test_locate_inscriptions_in_one_input() {
+        let tx = tx(
+            vec![
+                txin(0, &[]),
+                txin(1, &[mint_inscription(None), mint_inscription(Some(600))]),
+            ],
+            &[500, 500, 500],
+        );
+        assert_eq!(
+            locate(&tx, &[1_000, 500]),
+            vec![Some((2, 0)), Some((1, 100))]
+        );
+    }
+
+    #[test]
+    fn test_locate_inscriptions_spent_as_fee() {
+        let tx = tx(
+            vec![txin(0, &[]), txin(1, &[mint_inscription(None)])],
+            &[1_000],
+        );
+        assert_eq!(locate(&tx, &[1_000, 500]), vec![None]);
+    }
+
+    #[test]
+    fn test_check_parents_requires_spent_parent() {
+        let deploy_inscription_id = InscriptionId {
+            txid: Txid::all_zeros(),
+            index: 0,
+        };
+        let deploy_outpoint = OutPoint {
+            txid: Txid::all_zeros(),
+            vout: 7,
+        };
+        let mut state = IndexerState::new();
+        state.insert_tick(
+            deploy_inscription_id,
+            DeployRecord::new_deploy_record(
+                "BITS".to_string(),
+                10000,
+                None,
+                Some("fixed".to_string()),
+                0,
+                false,
+                vec![],
+            ),
+            Some(SatPoint {
+                outpoint: deploy_outpoint,
+                offset: 0,
+            }),
+        );
+
+        let mut child = mint_inscription(None);
+        child.parents = vec![inscription_id_to_bytes(deploy_inscription_id)];
+        let check = |tx: &Transaction| {
+            let envelope = ParsedEnvelope::from_transaction(tx).remove(0);
+            let parents = inscription::parents(&envelope.payload);
+            assert_eq!(parents, vec![deploy_inscription_id]);
+            let spent = Indexer::spent_inscriptions(&state, tx);
+            Indexer::check_parents(&spent, &parents, Some(deploy_inscription_id))
+        };
+
+        // The reveal transaction spends the deploy inscription.
+        let reveal = tx(
+            vec![txin(deploy_outpoint.vout, &[]), txin(1, &[child.clone()])],
+            &[546, 546],
+        );
+        assert!(check(&reveal).is_ok());
+
+        // A forged parent tag which does not spend the deploy inscription.
+        let forged = tx(vec![txin(0, &[]), txin(1, &[child])], &[546, 546]);
+        assert!(check(&forged).is_err());
+
+        // The other parents are not allowed.
+        let spent = BTreeSet::from([deploy_inscription_id]);
+        assert!(Indexer::check_parents(&spent, &[deploy_inscription_id], None).is_err());
+        assert!(Indexer::check_parents(&BTreeSet::new(), &[], None).is_ok());
+    }
+
+    #[test]
+    fn test_deploy_requires_confirmed_generator() {
+        let inscription_id = |index| InscriptionId {
+            txid: Txid::all_zeros(),
+            index,
+        };
+        let deploy_record = DeployRecord::new_deploy_record(
+            "BITS".to_string(),
+            10000,
+            Some(format!("/inscription/{}", inscription_id(0))),
+            None,
+            0,
+            false,
+            vec![],
+        );
+        // The deploy is in block 100, the generator is revealed in `generator_height`.
+        let deploy = |state: &mut IndexerState, index, generator_height| {
+            Indexer::deploy(
+                state,
+                &BTreeSet::new(),
+                &[],
+                inscription_id(index),
+                deploy_record.clone(),
+                None,
+                |deploy_record| {
+                    Wallet::ensure_confirmed(
+                        deploy_record.generator_inscription_id().unwrap(),
+                        Some(generator_height),
+                        100,
+                    )
+                },
+            )
+        };
+
+        // The generator revealed after the deploy makes it invalid, it does not take the tick.
+        let mut state = IndexerState::new();
+        assert!(error::is_invalid(&deploy(&mut state, 1, 101).unwrap_err()));
+        assert_eq!(state.deployed_by("BITS"), None);
+
+        assert!(deploy(&mut state, 2, 100).is_ok());
+        assert_eq!(state.deployed_by("BITS"), Some(inscription_id(2)));
+    }
+
+    #[test]
+    fn test_broken_generator_deploy_does_not_take_tick() {
+        let inscription_id = |index| InscriptionId {
+            txid: Txid::all_zeros(),
+            index,
+        };
+        let deploy_record = DeployRecord::new_deploy_record(
+            "BITS".to_string(),
+            10000,
+            Some(format!("/inscription/{}", inscription_id(0))),
+            None,
+            0,
+            false,
+            vec![],
+        );
+        let mut state = IndexerState::new();
+
+        let broken = Indexer::deploy(
+            &mut state,
+            &BTreeSet::new(),
+            &[],
+            inscription_id(1),
+            deploy_record.clone(),
+            None,
+            |_| Err(GeneratorError::Instantiation("not wasm".to_string()).into()),
+        );
+        assert!(error::is_invalid(&broken.unwrap_err()));
+        assert_eq!(state.deployed_by("BITS"), None);
+
+        // The later deploy with a valid generator takes the tick.
+        Indexer::deploy(
+            &mut state,
+            &BTreeSet::new(),
+            &[],
+            inscription_id(2),
+            deploy_record,
+            None,
+            |_| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(state.deployed_by("BITS"), Some(inscription_id(2)));
+    }
+
+    #[test]
+    fn test_unsupported_abi_deploy_does_not_take_tick() {
+        let inscription_id = InscriptionId {
+            txid: Txid::all_zeros(),
+            index: 1,
+        };
+        let bytecode = b"\0asm".to_vec();
+        let mut metadata = GeneratorMetadata::new("test".to_string(), &bytecode);
+        metadata.abi_version = ABI_VERSION + 1;
+
+        // The loader checks the metadata of the generator when the deploy is indexed.
+        let mut state = IndexerState::new();
+        let deploy = Indexer::deploy(
+            &mut state,
+            &BTreeSet::new(),
+            &[],
+            inscription_id,
+            DeployRecord::new_deploy_record(
+                "BITS".to_string(),
+                10000,
+                Some(format!("/inscription/{}", inscription_id)),
+                None,
+                0,
+                false,
+                vec![],
+            ),
+            None,
+            |_| metadata.check(&bytecode).map_err(error::invalid),
+        );
+        assert!(error::is_invalid(&deploy.unwrap_err()));
+        assert_eq!(state.deployed_by("BITS"), None);
+    }
+}
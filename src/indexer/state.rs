@@ -0,0 +1,636 @@
+use crate::operation::{DeployRecord, Operation};
+use crate::sft::SFT;
+use crate::tick::normalize_tick;
+use bitcoin::{BlockHash, OutPoint};
+use ciborium::Value;
+use ord::InscriptionId;
+use ordinals::SatPoint;
+use primitive_types::H256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickEntry {
+    pub deploy_inscription_id: InscriptionId,
+    pub deploy: DeployRecord,
+    // The amount minted so far
+    pub minted: u64,
+    // The number of mints of each attributes hash
+    pub repeats: BTreeMap<H256, u64>,
+    // Where the deploy inscription is, None if it is spent as fee. The mints may be its children.
+    #[serde(default)]
+    pub satpoint: Option<SatPoint>,
+}
+
+impl TickEntry {
+    pub fn repeated(&self, attributes_hash: &H256) -> u64 {
+        self.repeats.get(attributes_hash).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SFTEntry {
+    pub inscription_id: InscriptionId,
+    pub sft: SFT,
+    // The attributes generated by the indexer_generate of the generator after the mint is confirmed.
+    pub indexer_attributes: Option<Value>,
+    pub satpoint: SatPoint,
+    // The address of the output which holds the SFT, None if the script is not an address.
+    pub owner: Option<String>,
+}
+
+/// The previous value of a state entry, it is restored when the block is rolled back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Undo {
+    Tick {
+        tick: String,
+        previous: Option<TickEntry>,
+    },
+    Sft {
+        inscription_id: InscriptionId,
+        previous: Option<SFTEntry>,
+    },
+    Invalid {
+        inscription_id: InscriptionId,
+        previous: Option<String>,
+    },
+    Lineage {
+        inscription_id: InscriptionId,
+        previous: Option<Vec<InscriptionId>>,
+    },
+    // The operation of an inscription of the block, it is deleted by the rollback.
+    Operation {
+        inscription_id: InscriptionId,
+    },
+}
+
+/// The undo log of an indexed block which is not final yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockUndo {
+    pub height: u64,
+    pub block_hash: BlockHash,
+    undo: Vec<Undo>,
+}
+
+/// The keys of the state entries which are changed since the state was saved,
+/// the store writes their current values.
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub ticks: BTreeSet<String>,
+    pub sfts: BTreeSet<InscriptionId>,
+    pub invalid: BTreeSet<InscriptionId>,
+    pub lineage: BTreeSet<InscriptionId>,
+    // The operations are only kept until they are saved, None if the operation is rolled back
+    pub operations: BTreeMap<InscriptionId, Option<Operation>>,
+    // The heights of the undo logs which are added or pruned
+    pub blocks: BTreeSet<u64>,
+}
+
+/// The Bitseed protocol state built from the chain.
+#[derive(Debug, Clone, Default)]
+pub struct IndexerState {
+    // The last indexed block height
+    height: Option<u64>,
+    ticks: BTreeMap<String, TickEntry>,
+    sfts: BTreeMap<InscriptionId, SFTEntry>,
+    outpoints: BTreeMap<OutPoint, BTreeSet<InscriptionId>>,
+    // Owner address -> the SFTs held by the address
+    owners: BTreeMap<String, BTreeSet<InscriptionId>>,
+    // The ticks whose deploy inscriptions are at the outpoint
+    deploys: BTreeMap<OutPoint, BTreeSet<String>>,
+    invalid: BTreeMap<InscriptionId, String>,
+    // SFT inscription id -> the inscription ids of the SFTs burned to create it by split or merge
+    lineage: BTreeMap<InscriptionId, Vec<InscriptionId>>,
+    // The undo logs of the recent blocks which can be rolled back, oldest first
+    blocks: VecDeque<BlockUndo>,
+    // The last block which is final, its undo log is pruned
+    final_block: Option<(u64, BlockHash)>,
+    // The undo log of the block being indexed
+    pending: Vec<Undo>,
+    changes: Changes,
+}
+
+impl IndexerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild the state from the stored entries.
+    pub fn restore(
+        height: Option<u64>,
+        final_block: Option<(u64, BlockHash)>,
+        ticks: BTreeMap<String, TickEntry>,
+        sfts: Vec<SFTEntry>,
+        invalid: BTreeMap<InscriptionId, String>,
+        lineage: BTreeMap<InscriptionId, Vec<InscriptionId>>,
+        blocks: VecDeque<BlockUndo>,
+    ) -> Self {
+        let mut state = Self {
+            height,
+            final_block,
+            invalid,
+            lineage,
+            blocks,
+            ..Self::default()
+        };
+        for (tick, entry) in ticks {
+            state.put_tick(tick, entry);
+        }
+        for entry in sfts {
+            state.put_sft(entry);
+        }
+        state
+    }
+
+    /// The last indexed block, the store compares it to detect the updates of other processes.
+    pub fn tip(&self) -> Option<(u64, BlockHash)> {
+        self.last_block()
+            .map(|block| (block.height, block.block_hash))
+            .or(self.final_block)
+    }
+
+    /// Take the changes since the last call, they must be saved to the store.
+    pub fn take_changes(&mut self) -> Changes {
+        std::mem::take(&mut self.changes)
+    }
+
+    pub fn height(&self) -> Option<u64> {
+        self.height
+    }
+
+    /// The last indexed block which can be rolled back.
+    pub fn last_block(&self) -> Option<&BlockUndo> {
+        self.blocks.back()
+    }
+
+    pub fn final_block(&self) -> Option<(u64, BlockHash)> {
+        self.final_block
+    }
+
+    /// Finish the block being indexed and keep its undo log, only the last `confirmations`
+    /// blocks can be rolled back, the older blocks are final.
+    pub fn commit_block(&mut self, height: u64, block_hash: BlockHash, confirmations: u64) {
+        self.blocks.push_back(BlockUndo {
+            height,
+            block_hash,
+            undo: std::mem::take(&mut self.pending),
+        });
+        self.height = Some(height);
+        self.changes.blocks.insert(height);
+
+        while self.blocks.len() as u64 > confirmations.max(1) {
+            if let Some(block) = self.blocks.pop_front() {
+                self.final_block = Some((block.height, block.block_hash));
+                self.changes.blocks.insert(block.height);
+            }
+        }
+    }
+
+    /// Discard the changes of the block being indexed.
+    pub fn abort_block(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.revert(pending);
+    }
+
+    /// Roll back the last indexed block, returns None if there is no undo log.
+    pub fn rollback_block(&mut self) -> Option<BlockUndo> {
+        self.abort_block();
+        let mut block = self.blocks.pop_back()?;
+        self.changes.blocks.insert(block.height);
+        self.revert(std::mem::take(&mut block.undo));
+        self.height = block.height.checked_sub(1);
+        Some(block)
+    }
+
+    pub fn get_block(&self, height: u64) -> Option<&BlockUndo> {
+        self.blocks.iter().find(|block| block.height == height)
+    }
+
+    pub fn get_tick(&self, tick: &str) -> Option<&TickEntry> {
+        self.ticks.get(&normalize_tick(tick))
+    }
+
+    /// Get the inscription which deployed the tick, the first valid deploy wins.
+    pub fn deployed_by(&self, tick: &str) -> Option<InscriptionId> {
+        self.get_tick(tick).map(|entry| entry.deploy_inscription_id)
+    }
+
+    pub fn ticks(&self) -> impl Iterator<Item = &TickEntry> {
+        self.ticks.values()
+    }
+
+    pub fn insert_tick(
+        &mut self,
+        deploy_inscription_id: InscriptionId,
+        deploy: DeployRecord,
+        satpoint: Option<SatPoint>,
+    ) {
+        let tick = normalize_tick(&deploy.tick);
+        self.record_tick(&tick);
+        self.put_tick(
+            tick,
+            TickEntry {
+                deploy_inscription_id,
+                deploy,
+                minted: 0,
+                repeats: BTreeMap::new(),
+                satpoint,
+            },
+        );
+    }
+
+    /// Move the deploy inscription of the tick, None if it is spent as fee.
+    pub fn move_tick(&mut self, tick: &str, satpoint: Option<SatPoint>) {
+        let tick = normalize_tick(tick);
+        self.record_tick(&tick);
+        if let Some(mut entry) = self.take_tick(&tick) {
+            entry.satpoint = satpoint;
+            self.put_tick(tick, entry);
+        }
+    }
+
+    pub fn ticks_at_outpoint(&self, outpoint: &OutPoint) -> Vec<&TickEntry> {
+        self.deploys
+            .get(outpoint)
+            .map(|ticks| {
+                ticks
+                    .iter()
+                    .filter_map(|tick| self.ticks.get(tick))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn has_ticks_at_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.deploys.contains_key(outpoint)
+    }
+
+    pub fn add_mint(&mut self, tick: &str, amount: u64, attributes_hash: H256) {
+        let tick = normalize_tick(tick);
+        self.record_tick(&tick);
+        if let Some(entry) = self.ticks.get_mut(&tick) {
+            entry.minted += amount;
+            *entry.repeats.entry(attributes_hash).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get_sft(&self, inscription_id: &InscriptionId) -> Option<&SFTEntry> {
+        self.sfts.get(inscription_id)
+    }
+
+    pub fn sfts(&self) -> impl Iterator<Item = &SFTEntry> {
+        self.sfts.values()
+    }
+
+    pub fn sfts_by_owner(&self, owner: &str) -> Vec<&SFTEntry> {
+        self.owners
+            .get(owner)
+            .map(|ids| ids.iter().filter_map(|id| self.sfts.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn sfts_at_outpoint(&self, outpoint: &OutPoint) -> Vec<&SFTEntry> {
+        self.outpoints
+            .get(outpoint)
+            .map(|ids| ids.iter().filter_map(|id| self.sfts.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn has_sfts_at_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.outpoints.contains_key(outpoint)
+    }
+
+    pub fn insert_sft(&mut self, entry: SFTEntry) {
+        self.record_sft(&entry.inscription_id);
+        self.put_sft(entry);
+    }
+
+    pub fn remove_sft(&mut self, inscription_id: &InscriptionId) -> Option<SFTEntry> {
+        self.record_sft(inscription_id);
+        self.take_sft(inscription_id)
+    }
+
+    pub fn move_sft(
+        &mut self,
+        inscription_id: &InscriptionId,
+        satpoint: SatPoint,
+        owner: Option<String>,
+    ) {
+        if let Some(mut entry) = self.remove_sft(inscription_id) {
+            entry.satpoint = satpoint;
+            entry.owner = owner;
+            self.insert_sft(entry);
+        }
+    }
+
+    pub fn is_invalid(&self, inscription_id: &InscriptionId) -> bool {
+        self.invalid.contains_key(inscription_id)
+    }
+
+    pub fn invalid(&self) -> impl Iterator<Item = (&InscriptionId, &String)> {
+        self.invalid.iter()
+    }
+
+    pub fn get_invalid(&self, inscription_id: &InscriptionId) -> Option<&String> {
+        self.invalid.get(inscription_id)
+    }
+
+    pub fn insert_invalid(&mut self, inscription_id: InscriptionId, reason: String) {
+        self.pending.push(Undo::Invalid {
+            inscription_id,
+            previous: self.invalid.get(&inscription_id).cloned(),
+        });
+        self.changes.invalid.insert(inscription_id);
+        self.invalid.insert(inscription_id, reason);
+    }
+
+    /// Get the inscription ids of the SFTs which are burned to create the SFT.
+    pub fn get_lineage(&self, inscription_id: &InscriptionId) -> Option<&Vec<InscriptionId>> {
+        self.lineage.get(inscription_id)
+    }
+
+    pub fn insert_lineage(&mut self, inscription_id: InscriptionId, sources: Vec<InscriptionId>) {
+        self.pending.push(Undo::Lineage {
+            inscription_id,
+            previous: self.lineage.get(&inscription_id).cloned(),
+        });
+        self.changes.lineage.insert(inscription_id);
+        self.lineage.insert(inscription_id, sources);
+    }
+
+    /// Record the operation of an inscription of the block being indexed, the operations
+    /// are not kept in memory after they are saved.
+    pub fn insert_operation(&mut self, inscription_id: InscriptionId, operation: Operation) {
+        self.pending.push(Undo::Operation { inscription_id });
+        self.changes
+            .operations
+            .insert(inscription_id, Some(operation));
+    }
+
+    /// Get the operation which is not saved yet, Some(None) if it is rolled back.
+    pub fn unsaved_operation(&self, inscription_id: &InscriptionId) -> Option<Option<&Operation>> {
+        self.changes
+            .operations
+            .get(inscription_id)
+            .map(|operation| operation.as_ref())
+    }
+
+    fn record_tick(&mut self, tick: &str) {
+        self.pending.push(Undo::Tick {
+            tick: tick.to_string(),
+            previous: self.ticks.get(tick).cloned(),
+        });
+        self.changes.ticks.insert(tick.to_string());
+    }
+
+    fn record_sft(&mut self, inscription_id: &InscriptionId) {
+        self.pending.push(Undo::Sft {
+            inscription_id: *inscription_id,
+            previous: self.sfts.get(inscription_id).cloned(),
+        });
+        self.changes.sfts.insert(*inscription_id);
+    }
+
+    /// Restore the previous values in the reverse order of the changes.
+    fn revert(&mut self, undo: Vec<Undo>) {
+        for undo in undo.into_iter().rev() {
+            match undo {
+                Undo::Tick { tick, previous } => {
+                    self.changes.ticks.insert(tick.clone());
+                    self.take_tick(&tick);
+                    if let Some(entry) = previous {
+                        self.put_tick(tick, entry);
+                    }
+                }
+                Undo::Sft {
+                    inscription_id,
+                    previous,
+                } => {
+                    self.changes.sfts.insert(inscription_id);
+                    self.take_sft(&inscription_id);
+                    if let Some(entry) = previous {
+                        self.put_sft(entry);
+                    }
+                }
+                Undo::Invalid {
+                    inscription_id,
+                    previous,
+                } => {
+                    self.changes.invalid.insert(inscription_id);
+                    match previous {
+                        Some(reason) => {
+                            self.invalid.insert(inscription_id, reason);
+                        }
+                        None => {
+                            self.invalid.remove(&inscription_id);
+                        }
+                    }
+                }
+                Undo::Lineage {
+                    inscription_id,
+                    previous,
+                } => {
+                    self.changes.lineage.insert(inscription_id);
+                    match previous {
+                        Some(sources) => {
+                            self.lineage.insert(inscription_id, sources);
+                        }
+                        None => {
+                            self.lineage.remove(&inscription_id);
+                        }
+                    }
+                }
+                Undo::Operation { inscription_id } => {
+                    self.changes.operations.insert(inscription_id, None);
+                }
+            }
+        }
+    }
+
+    fn put_tick(&mut self, tick: String, entry: TickEntry) {
+        self.take_tick(&tick);
+        if let Some(satpoint) = entry.satpoint {
+            self.deploys
+                .entry(satpoint.outpoint)
+                .or_default()
+                .insert(tick.clone());
+        }
+        self.ticks.insert(tick, entry);
+    }
+
+    fn take_tick(&mut self, tick: &str) -> Option<TickEntry> {
+        let entry = self.ticks.remove(tick)?;
+        if let Some(outpoint) = entry.satpoint.map(|satpoint| satpoint.outpoint) {
+            if let Some(ticks) = self.deploys.get_mut(&outpoint) {
+                ticks.remove(tick);
+                if ticks.is_empty() {
+                    self.deploys.remove(&outpoint);
+                }
+            }
+        }
+        Some(entry)
+    }
+
+    fn put_sft(&mut self, entry: SFTEntry) {
+        self.take_sft(&entry.inscription_id);
+        self.outpoints
+            .entry(entry.satpoint.outpoint)
+            .or_default()
+            .insert(entry.inscription_id);
+        if let Some(owner) = &entry.owner {
+            self.owners
+                .entry(owner.clone())
+                .or_default()
+                .insert(entry.inscription_id);
+        }
+        self.sfts.insert(entry.inscription_id, entry);
+    }
+
+    fn take_sft(&mut self, inscription_id: &InscriptionId) -> Option<SFTEntry> {
+        let entry = self.sfts.remove(inscription_id)?;
+        self.detach_outpoint(&entry);
+        self.detach_owner(&entry);
+        Some(entry)
+    }
+
+    fn detach_owner(&mut self, entry: &SFTEntry) {
+        if let Some(owner) = &entry.owner {
+            if let Some(ids) = self.owners.get_mut(owner) {
+                ids.remove(&entry.inscription_id);
+                if ids.is_empty() {
+                    self.owners.remove(owner);
+                }
+            }
+        }
+    }
+
+    fn detach_outpoint(&mut self, entry: &SFTEntry) {
+        if let Some(ids) = self.outpoints.get_mut(&entry.satpoint.outpoint) {
+            ids.remove(&entry.inscription_id);
+            if ids.is_empty() {
+                self.outpoints.remove(&entry.satpoint.outpoint);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use std::str::FromStr;
+
+    fn inscription_id(index: u32) -> InscriptionId {
+        InscriptionId::from_str(&format!(
+            "6f55475ce65054aa8371d618d217da8c9a764cecdaf4debcbce8d6312fe6b4d8i{}",
+            index
+        ))
+        .unwrap()
+    }
+
+    fn sft_entry(index: u32, vout: u32) -> SFTEntry {
+        SFTEntry {
+            inscription_id: inscription_id(index),
+            sft: SFT::new("BITS".to_string(), 1000, None, None),
+            indexer_attributes: None,
+            satpoint: SatPoint {
+                outpoint: OutPoint {
+                    txid: inscription_id(index).txid,
+                    vout,
+                },
+                offset: 0,
+            },
+            owner: None,
+        }
+    }
+
+    fn block_hash(height: u64) -> BlockHash {
+        BlockHash::hash(&height.to_le_bytes())
+    }
+
+    #[test]
+    fn test_rollback_block() {
+        let mut state = IndexerState::new();
+        let deploy = DeployRecord::new_deploy_record(
+            "BITS".to_string(),
+            10000,
+            None,
+            None,
+            0,
+            false,
+            vec![],
+        );
+        let deployed = sft_entry(0, 0).satpoint;
+        state.insert_tick(inscription_id(0), deploy, Some(deployed));
+        state.commit_block(1, block_hash(1), 6);
+        assert_eq!(state.ticks_at_outpoint(&deployed.outpoint).len(), 1);
+        let committed = state.clone();
+
+        state.add_mint("bits", 1000, H256::zero());
+        state.insert_sft(sft_entry(1, 0));
+        state.insert_invalid(inscription_id(2), "invalid".to_string());
+        state.insert_lineage(inscription_id(1), vec![inscription_id(3)]);
+        state.insert_operation(
+            inscription_id(1),
+            Operation::Mint(crate::operation::MintRecord {
+                sft: sft_entry(1, 0).sft,
+                user_input: None,
+            }),
+        );
+        state.commit_block(2, block_hash(2), 6);
+        let changes = state.take_changes();
+        assert!(changes.sfts.contains(&inscription_id(1)));
+        assert!(changes.operations[&inscription_id(1)].is_some());
+        assert_eq!(changes.blocks, BTreeSet::from([2]));
+
+        let moved = sft_entry(1, 1);
+        state.move_sft(
+            &moved.inscription_id,
+            moved.satpoint,
+            Some("bob".to_string()),
+        );
+        state.move_tick("bits", Some(moved.satpoint));
+        state.commit_block(3, block_hash(3), 6);
+        assert_eq!(state.sfts_at_outpoint(&moved.satpoint.outpoint).len(), 1);
+        assert_eq!(state.sfts_by_owner("bob").len(), 1);
+        assert_eq!(state.ticks_at_outpoint(&moved.satpoint.outpoint).len(), 1);
+        assert!(!state.has_ticks_at_outpoint(&deployed.outpoint));
+
+        assert_eq!(state.rollback_block().unwrap().height, 3);
+        assert_eq!(state.height(), Some(2));
+        assert_eq!(state.get_sft(&inscription_id(1)), Some(&sft_entry(1, 0)));
+        assert!(!state.has_sfts_at_outpoint(&moved.satpoint.outpoint));
+        assert!(state.sfts_by_owner("bob").is_empty());
+        assert!(!state.has_ticks_at_outpoint(&moved.satpoint.outpoint));
+        assert!(state.has_ticks_at_outpoint(&deployed.outpoint));
+
+        assert_eq!(state.rollback_block().unwrap().height, 2);
+        assert_eq!(state.height(), Some(1));
+        assert_eq!(state.get_tick("BITS"), committed.get_tick("BITS"));
+        assert!(state.get_sft(&inscription_id(1)).is_none());
+        assert!(!state.is_invalid(&inscription_id(2)));
+        assert!(state.get_lineage(&inscription_id(1)).is_none());
+
+        // The rolled back entries are changed, so the store removes them.
+        assert_eq!(state.unsaved_operation(&inscription_id(1)), Some(None));
+        let changes = state.take_changes();
+        assert!(changes.sfts.contains(&inscription_id(1)));
+        assert!(changes.lineage.contains(&inscription_id(1)));
+        assert_eq!(changes.operations[&inscription_id(1)], None);
+        assert_eq!(changes.blocks, BTreeSet::from([2, 3]));
+    }
+
+    #[test]
+    fn test_final_blocks() {
+        let mut state = IndexerState::new();
+        for height in 1..=5 {
+            state.commit_block(height, block_hash(height), 2);
+        }
+
+        assert_eq!(state.final_block(), Some((3, block_hash(3))));
+        assert!(state.rollback_block().is_some());
+        assert!(state.rollback_block().is_some());
+        assert!(state.rollback_block().is_none());
+        assert_eq!(state.height(), Some(3));
+    }
+}
@@ -0,0 +1,377 @@
+use crate::error::{self, InvalidOperation};
+use crate::generator::metadata::abi_attributes;
+use crate::generator::{
+    hash, Generator, GeneratorLoader, IndexerSeed, InscribeGenerateOutput, InscribeSeed,
+};
+use crate::indexer::SFTEntry;
+use crate::operation::{DeployRecord, MergeRecord, MintRecord, SplitRecord};
+use crate::sft::SFT;
+use crate::wallet::Wallet;
+use anyhow::{anyhow, ensure, Result};
+use bitcoin::{Address, Transaction, TxOut};
+use bitcoincore_rpc::RpcApi;
+use ciborium::Value;
+use ord::inscriptions::ParsedEnvelope;
+use ord::InscriptionId;
+use std::collections::{BTreeSet, HashMap};
+
+/// MintValidator verifies the confirmed mint inscriptions with the generator of the deploy.
+pub struct MintValidator {
+    wallet: Wallet,
+    loader: GeneratorLoader,
+    generators: HashMap<String, Box<dyn Generator>>,
+}
+
+impl MintValidator {
+    pub fn new(wallet: Wallet) -> Self {
+        Self {
+            loader: GeneratorLoader::new(wallet.clone()),
+            wallet,
+            generators: HashMap::new(),
+        }
+    }
+
+    /// Verify the mint inscription at `inscription_index` of the reveal transaction.
+    /// `recipient` is the output which receives the minted SFT.
+    pub fn verify_mint(
+        &mut self,
+        deploy: &DeployRecord,
+        reveal_tx: &Transaction,
+        inscription_index: usize,
+        recipient: &TxOut,
+        mint: &MintRecord,
+    ) -> Result<()> {
+        deploy
+            .check_user_input(mint.user_input.as_deref())
+            .map_err(error::invalid)?;
+
+        let seed = self.inscribe_seed(reveal_tx, inscription_index)?;
+        let recipient = self.recipient_address(recipient)?;
+
+        let generator = self.load_generator(deploy)?;
+        // The generator compares the encoded outputs, the attributes are sent in the form the
+        // generator encodes them.
+        let inscribe_output = InscribeGenerateOutput {
+            amount: mint.sft.amount,
+            attributes: abi_attributes(generator.abi_version(), mint.sft.attributes.clone()),
+            content: mint.sft.content.clone(),
+        };
+
+        let verified = generator.inscribe_verify(
+            &deploy.deploy_args,
+            &seed,
+            &recipient,
+            mint.user_input.clone(),
+            inscribe_output,
+        )?;
+        if !verified {
+            return Err(InvalidOperation::new(format!(
+                "The mint output does not match the generator output of tick {}",
+                deploy.tick
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Run the indexer_generate of the deploy generator for the confirmed mint,
+    /// returns None if the generator does not support it.
+    pub fn indexer_generate(
+        &mut self,
+        deploy: &DeployRecord,
+        seed: &IndexerSeed,
+        recipient: &TxOut,
+    ) -> Result<Option<Value>> {
+        let recipient = self.recipient_address(recipient)?;
+        let generator = self.load_generator(deploy)?;
+        if !generator.has_indexer_generate()? {
+            return Ok(None);
+        }
+
+        let output = generator.indexer_generate(deploy.deploy_args.clone(), seed, recipient)?;
+        Ok(output.attributes)
+    }
+
+    fn recipient_address(&self, recipient: &TxOut) -> Result<Address> {
+        Address::from_script(&recipient.script_pubkey, self.wallet.chain().network()).map_err(|e| {
+            InvalidOperation::new(format!("The mint recipient is not a valid address: {}", e))
+                .into()
+        })
+    }
+
+    /// Rebuild the seed of the mint from the first input of its commit transaction.
+    /// The transactions are confirmed before the reveal transaction, so bitcoind must know them.
+    fn inscribe_seed(
+        &self,
+        reveal_tx: &Transaction,
+        inscription_index: usize,
+    ) -> Result<InscribeSeed> {
+        let envelope = ParsedEnvelope::from_transaction(reveal_tx)
+            .into_iter()
+            .nth(inscription_index)
+            .ok_or_else(|| anyhow!("Inscription not found in the transaction"))?;
+        let reveal_input = reveal_tx
+            .input
+            .get(envelope.input as usize)
+            .ok_or_else(|| anyhow!("The reveal input of the inscription not found"))?;
+
+        let commit_tx = self
+            .wallet
+            .get_raw_transaction(&reveal_input.previous_output.txid)?;
+        let seed_utxo = commit_tx
+            .input
+            .first()
+            .ok_or_else(|| anyhow!("The commit transaction has no input"))?
+            .previous_output;
+        if seed_utxo.is_null() {
+            return Err(InvalidOperation::new("The commit transaction is a coinbase").into());
+        }
+
+        let block_hash = self
+            .wallet
+            .bitcoin_client()?
+            .get_raw_transaction_info(&seed_utxo.txid, None)?
+            .blockhash
+            .ok_or_else(|| anyhow!("seed utxo has no blockhash"))?;
+
+        Ok(InscribeSeed::new(block_hash, seed_utxo))
+    }
+
+    /// Load the generator of the deploy in the block `height`, a deploy whose generator can not
+    /// be loaded is invalid. The generator is kept for the mints of the deploy.
+    pub fn check_deploy(&mut self, deploy: &DeployRecord, height: u64) -> Result<()> {
+        let generator = self.loader.load_deploy(deploy, Some(height))?;
+        self.generators.insert(Self::key(deploy), generator);
+        Ok(())
+    }
+
+    /// The factories are configured by the deploy args, so they are cached by the args too.
+    fn key(deploy: &DeployRecord) -> String {
+        match (&deploy.generator, &deploy.factory) {
+            (Some(generator), _) => generator.clone(),
+            (None, factory) => format!(
+                "/factory/{}/{:x}",
+                factory.as_deref().unwrap_or_default(),
+                hash::sha3_256_of(&deploy.deploy_args)
+            ),
+        }
+    }
+
+    /// The generator of a valid deploy, it is checked when the deploy is indexed.
+    fn load_generator(&mut self, deploy: &DeployRecord) -> Result<&dyn Generator> {
+        let key = Self::key(deploy);
+        if !self.generators.contains_key(&key) {
+            let generator = self.loader.load_deploy(deploy, None)?;
+            self.generators.insert(key.clone(), generator);
+        }
+        Ok(self
+            .generators
+            .get(&key)
+            .expect("the generator should be loaded")
+            .as_ref())
+    }
+}
+
+/// Validate a split transaction: the single burned SFT is split into the outputs,
+/// the amount is conserved and the tick, attributes and content are not changed.
+pub fn validate_split(burned: &[SFTEntry], outputs: &[&SplitRecord]) -> Result<()> {
+    ensure!(
+        burned.len() == 1,
+        "A split must burn exactly one SFT, but it burns {}",
+        burned.len()
+    );
+    ensure!(!outputs.is_empty(), "A split must have at least one output");
+
+    let source = &burned[0].sft;
+    for output in outputs {
+        check_original(&output.original, burned)?;
+        source.ensure_same_kind(&output.sft)?;
+    }
+
+    let output_amount = sum_amount(outputs.iter().map(|output| &output.sft))?;
+    ensure!(
+        output_amount == source.amount,
+        "The split amount {} is not equal to the burned amount {}",
+        output_amount,
+        source.amount
+    );
+
+    Ok(())
+}
+
+/// Validate a merge transaction: the burned SFTs are merged into the single output,
+/// the amount is conserved and the tick, attributes and content are not changed.
+pub fn validate_merge(burned: &[SFTEntry], outputs: &[&MergeRecord]) -> Result<()> {
+    ensure!(
+        burned.len() > 1,
+        "A merge must burn at least two SFTs, but it burns {}",
+        burned.len()
+    );
+    ensure!(
+        outputs.len() == 1,
+        "A merge must have exactly one output, but it has {}",
+        outputs.len()
+    );
+
+    let merged = outputs[0];
+    check_original(&merged.original, burned)?;
+    for source in burned {
+        merged.sft.ensure_same_kind(&source.sft)?;
+    }
+
+    let burned_amount = sum_amount(burned.iter().map(|source| &source.sft))?;
+    ensure!(
+        merged.sft.amount == burned_amount,
+        "The merge amount {} is not equal to the burned amount {}",
+        merged.sft.amount,
+        burned_amount
+    );
+
+    Ok(())
+}
+
+/// The original of a split or merge must list the burned SFTs, it can not be empty.
+fn check_original(original: &[InscriptionId], burned: &[SFTEntry]) -> Result<()> {
+    let sources: BTreeSet<InscriptionId> =
+        burned.iter().map(|entry| entry.inscription_id).collect();
+    ensure!(
+        !original.is_empty() && original.iter().copied().collect::<BTreeSet<_>>() == sources,
+        "The original {:?} does not match the burned SFTs {:?}",
+        original,
+        sources
+    );
+    Ok(())
+}
+
+fn sum_amount<'a>(sfts: impl IntoIterator<Item = &'a SFT>) -> Result<u64> {
+    sfts.into_iter().try_fold(0u64, |total, sft| {
+        total
+            .checked_add(sft.amount)
+            .ok_or_else(|| anyhow!("The total amount overflows"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{hashes::Hash, OutPoint, Txid};
+    use ordinals::SatPoint;
+
+    fn sft(amount: u64) -> SFT {
+        SFT::new(
+            "BITS".to_string(),
+            amount,
+            Some(Value::Map(vec![(
+                Value::Text("height".to_string()),
+                Value::Integer(100.into()),
+            )])),
+            None,
+        )
+    }
+
+    fn inscription_id(index: u32) -> InscriptionId {
+        InscriptionId {
+            txid: Txid::all_zeros(),
+            index,
+        }
+    }
+
+    fn burned(amounts: &[u64]) -> Vec<SFTEntry> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| SFTEntry {
+                inscription_id: inscription_id(index as u32),
+                sft: sft(*amount),
+                indexer_attributes: None,
+                satpoint: SatPoint {
+                    outpoint: OutPoint::null(),
+                    offset: 0,
+                },
+                owner: None,
+            })
+            .collect()
+    }
+
+    fn original(burned: &[SFTEntry]) -> Vec<InscriptionId> {
+        burned.iter().map(|entry| entry.inscription_id).collect()
+    }
+
+    fn split(burned: &[SFTEntry], sft: SFT) -> SplitRecord {
+        SplitRecord {
+            sft,
+            original: original(burned),
+        }
+    }
+
+    fn merge(burned: &[SFTEntry], sft: SFT) -> MergeRecord {
+        MergeRecord {
+            sft,
+            original: original(burned),
+        }
+    }
+
+    #[test]
+    fn test_validate_split() {
+        let source = burned(&[1000]);
+        let outputs = [500, 300, 200].map(|amount| split(&source, sft(amount)));
+        assert!(validate_split(&source, &outputs.iter().collect::<Vec<_>>()).is_ok());
+
+        let outputs = [split(&source, sft(500)), split(&source, sft(600))];
+        assert!(validate_split(&source, &outputs.iter().collect::<Vec<_>>()).is_err());
+
+        let two = burned(&[1000, 1]);
+        assert!(validate_split(&two, &[&split(&two, sft(1001))]).is_err());
+
+        let mut other_tick = sft(500);
+        other_tick.tick = "OTHER".to_string();
+        let outputs = [split(&source, sft(500)), split(&source, other_tick)];
+        assert!(validate_split(&source, &outputs.iter().collect::<Vec<_>>()).is_err());
+
+        let mut other_attributes = sft(500);
+        other_attributes.attributes = None;
+        let outputs = [split(&source, sft(500)), split(&source, other_attributes)];
+        assert!(validate_split(&source, &outputs.iter().collect::<Vec<_>>()).is_err());
+    }
+
+    #[test]
+    fn test_validate_merge() {
+        let sources = burned(&[500, 300, 200]);
+        assert!(validate_merge(&sources, &[&merge(&sources, sft(1000))]).is_ok());
+
+        let sources = burned(&[500, 300]);
+        assert!(validate_merge(&sources, &[&merge(&sources, sft(1000))]).is_err());
+
+        let source = burned(&[1000]);
+        assert!(validate_merge(&source, &[&merge(&source, sft(1000))]).is_err());
+
+        let sources = burned(&[500, 500]);
+        let outputs = [merge(&sources, sft(500)), merge(&sources, sft(500))];
+        assert!(validate_merge(&sources, &outputs.iter().collect::<Vec<_>>()).is_err());
+
+        let sources = burned(&[u64::MAX, 1]);
+        assert!(validate_merge(&sources, &[&merge(&sources, sft(0))]).is_err());
+    }
+
+    #[test]
+    fn test_check_original() {
+        let source = burned(&[1000]);
+        let mut output = split(&source, sft(1000));
+        assert!(validate_split(&source, &[&output]).is_ok());
+
+        // The original is required, and it must list the burned SFTs.
+        output.original = vec![];
+        assert!(validate_split(&source, &[&output]).is_err());
+        output.original = vec![inscription_id(1)];
+        assert!(validate_split(&source, &[&output]).is_err());
+
+        let sources = burned(&[500, 500]);
+        let mut output = merge(&sources, sft(1000));
+        assert!(validate_merge(&sources, &[&output]).is_ok());
+        output.original = vec![];
+        assert!(validate_merge(&sources, &[&output]).is_err());
+        output.original = vec![inscription_id(0)];
+        assert!(validate_merge(&sources, &[&output]).is_err());
+    }
+}
@@ -1,10 +1,19 @@
 use {
     crate::{
-        generator::{self, GeneratorLoader, InscribeSeed},
+        compression::Compression,
+        deploy_args::DeployArgs,
+        generator::{
+            self,
+            metadata::{abi_attributes, GeneratorMetadata},
+            GeneratorLoader, InscribeSeed,
+        },
+        indexer::{Indexer, TickEntry},
+        inscription,
         operation::{AsSFT, DeployRecord, MergeRecord, MintRecord, Operation, SplitRecord},
         sft::{Content, SFT},
+        tick,
         wallet::Wallet,
-        GENERATOR_TICK,
+        GENERATOR_TICK, PROTOCOL,
     },
     anyhow::{anyhow, bail, ensure, Result},
     bitcoin::{
@@ -36,6 +45,13 @@ use {
 
 const TARGET_POSTAGE: Amount = Amount::from_sat(10_000);
 
+/// The OP_RETURN script of the output which receives the burned SFTs of split and merge.
+pub fn burn_script() -> ScriptBuf {
+    let msg_push_bytes = script::PushBytesBuf::try_from(PROTOCOL.as_bytes().to_vec())
+        .expect("burn message should fit");
+    ScriptBuf::new_op_return(&msg_push_bytes)
+}
+
 #[derive(Debug, Clone, Parser)]
 pub struct InscribeOptions {
     #[arg(
@@ -67,6 +83,11 @@ pub struct InscribeOptions {
         help = "Amount of postage to include in the inscription. Default `10000sat`."
     )]
     pub(crate) postage: Option<Amount>,
+    #[arg(
+        long,
+        help = "Do not check the tick in the Bitseed index before a deploy or a mint. The check syncs the index to the chain tip, which requires bitcoind to run with -txindex=1 and scans all the blocks from <INDEX_START_HEIGHT> on the first run."
+    )]
+    pub(crate) no_index_check: bool,
 }
 
 impl InscribeOptions {
@@ -123,6 +144,7 @@ pub struct Inscriber {
     option: InscribeOptions,
     inscriptions: Vec<Inscription>,
     inscriptions_to_burn: Vec<InscriptionId>,
+    parent: Option<InscriptionId>,
     satpoint: SatPoint,
     destination: Address,
 }
@@ -155,21 +177,40 @@ impl Inscriber {
             option,
             inscriptions: Vec::new(),
             inscriptions_to_burn: Vec::new(),
+            parent: None,
             satpoint,
             destination,
         })
     }
 
-    pub fn with_generator<P>(self, generator_name: String, generator_program: P) -> Result<Self>
+    pub fn with_generator<P>(
+        self,
+        metadata: GeneratorMetadata,
+        generator_program: P,
+        compression: Option<Compression>,
+    ) -> Result<Self>
     where
         P: AsRef<Path>,
     {
         let bytecode = std::fs::read(generator_program)?;
-        let content = Content::new(generator::CONTENT_TYPE.to_string(), bytecode);
-        let attributes = Value::Map(vec![(
-            Value::Text("name".to_string()),
-            Value::Text(generator_name.clone().into()),
-        )]);
+        ensure!(
+            bytecode.len() as u64 <= generator::MAX_BYTECODE_SIZE,
+            "The generator bytecode exceeds the limit of {} bytes",
+            generator::MAX_BYTECODE_SIZE
+        );
+        let metadata = GeneratorMetadata {
+            hash: Some(generator::hash::sha3_256_of(&bytecode)),
+            ..metadata
+        };
+        let attributes = metadata.to_attributes();
+        // Check the metadata the same way as the loader does.
+        GeneratorMetadata::from_attributes(&attributes)?.check(&bytecode)?;
+
+        let content_type = generator::CONTENT_TYPE.to_string();
+        let content = match compression {
+            Some(compression) => Content::compress(content_type, &bytecode, compression)?,
+            None => Content::new(content_type, bytecode),
+        };
 
         let mint_record = MintRecord {
             sft: SFT {
@@ -178,6 +219,7 @@ impl Inscriber {
                 attributes: Some(attributes),
                 content: Some(content),
             },
+            user_input: None,
         };
 
         Ok(self.with_operation(Operation::Mint(mint_record)))
@@ -190,6 +232,7 @@ impl Inscriber {
         generator: Option<InscriptionId>,
         factory: Option<String>,
         repeat: u64,
+        has_user_input: bool,
         deploy_args: Vec<u8>,
     ) -> Result<Self> {
         match (&generator, &factory) {
@@ -197,6 +240,21 @@ impl Inscriber {
             (None, None) => bail!("generator or factory must be provided"),
             _ => {}
         }
+
+        DeployArgs::from_cbor(&deploy_args)?;
+        if let Some(factory) = &factory {
+            generator::factory::load(factory, &deploy_args)?;
+        }
+
+        let tick = tick::validate_tick(&tick)?;
+        if let Some(tick_entry) = self.indexed_tick(&tick)? {
+            bail!(
+                "The tick {} is already deployed by inscription {}",
+                tick,
+                tick_entry.deploy_inscription_id
+            );
+        }
+
         //TODO check the generator exists.
         let deploy_record = DeployRecord {
             tick,
@@ -204,6 +262,7 @@ impl Inscriber {
             generator: generator.map(|generator| format!("/inscription/{}", generator)),
             factory,
             repeat,
+            has_user_input,
             deploy_args,
         };
         Ok(self.with_operation(Operation::Deploy(deploy_record)))
@@ -221,12 +280,10 @@ impl Inscriber {
             Operation::Deploy(deploy_record) => deploy_record,
             _ => bail!("deploy transaction must have a deploy operation"),
         };
+        deploy_record.check_user_input(user_input.as_deref())?;
 
-        let generator_loader = GeneratorLoader::new(self.wallet.clone());
-        let generator_id = deploy_record.generator.as_ref().ok_or_else(|| {
-            anyhow!("The deploy record does not have a generator inscription id, can not mint on Bitcoin")
-        })?;
-        let generator = generator_loader.load(generator_id)?;
+        let generator =
+            GeneratorLoader::new(self.wallet.clone()).load_deploy(&deploy_record, None)?;
 
         let seed_utxo = self.satpoint.outpoint;
         let btc_client = self.wallet.bitcoin_client()?;
@@ -245,20 +302,51 @@ impl Inscriber {
             &deploy_record.deploy_args,
             &seed,
             &destination,
-            user_input,
-        );
+            user_input.clone(),
+        )?;
 
         let sft = SFT {
-            tick: deploy_record.tick,
+            tick: deploy_record.tick.clone(),
             amount: output.amount,
-            attributes: output.attributes,
+            attributes: abi_attributes(generator.abi_version(), output.attributes),
             content: output.content,
         };
-        let mint_record = MintRecord { sft };
+
+        if !self.option.no_index_check {
+            let tick_entry = self.index_tick(deploy_inscription, &deploy_record.tick)?;
+            deploy_record.check_supply(tick_entry.minted, sft.amount)?;
+            deploy_record.check_repeat(tick_entry.repeated(&sft.attributes_hash()))?;
+        }
+
+        let mint_record = MintRecord { sft, user_input };
 
         Ok(self.with_operation(Operation::Mint(mint_record)))
     }
 
+    /// Get the mint state of the tick, it must be deployed by the deploy inscription.
+    fn index_tick(&self, deploy_inscription: InscriptionId, tick: &str) -> Result<TickEntry> {
+        let tick_entry = self
+            .indexed_tick(tick)?
+            .ok_or_else(|| anyhow!("The tick {} is not found in the index", tick))?;
+        ensure!(
+            tick_entry.deploy_inscription_id == deploy_inscription,
+            "The deploy inscription {} is not the valid deploy of tick {}",
+            deploy_inscription,
+            tick
+        );
+        Ok(tick_entry)
+    }
+
+    /// Catch up the index from the last indexed block and read the tick from the store.
+    /// Returns None if the index check is turned off.
+    fn indexed_tick(&self, tick: &str) -> Result<Option<TickEntry>> {
+        if self.option.no_index_check {
+            return Ok(None);
+        }
+        Indexer::sync(&self.wallet)?;
+        self.wallet.store()?.get_tick(tick)
+    }
+
     pub fn with_split(
         self,
         asset_inscription_id: InscriptionId,
@@ -282,6 +370,11 @@ impl Inscriber {
             "The total split amount exceeds the available SFT amount"
         );
 
+        // The outputs point to the content of the original SFT instead of copying it.
+        let content = sft
+            .content
+            .as_ref()
+            .map(|content| content.delegate_to(asset_inscription_id));
         let mut remaining_amount = sft.amount;
         let mut result = self.with_burn(asset_inscription_id);
 
@@ -292,9 +385,12 @@ impl Inscriber {
                 tick: sft.tick.clone(),
                 amount,
                 attributes: sft.attributes.clone(),
-                content: sft.content.clone(),
+                content: content.clone(),
+            };
+            let split_record = SplitRecord {
+                sft: split_sft,
+                original: vec![asset_inscription_id],
             };
-            let split_record = SplitRecord { sft: split_sft };
             result = result.with_operation(Operation::Split(split_record));
             remaining_amount -= amount;
 
@@ -303,9 +399,12 @@ impl Inscriber {
                     tick: sft.tick.clone(),
                     amount: remaining_amount,
                     attributes: sft.attributes.clone(),
-                    content: sft.content.clone(),
+                    content: content.clone(),
+                };
+                let split_record = SplitRecord {
+                    sft: remaining_sft,
+                    original: vec![asset_inscription_id],
                 };
-                let split_record = SplitRecord { sft: remaining_sft };
                 result = result.with_operation(Operation::Split(split_record));
             }
         }
@@ -322,7 +421,7 @@ impl Inscriber {
         let mut sft_to_merge = Vec::new();
         let mut result = self;
 
-        for inscription_id in sft_inscription_ids {
+        for inscription_id in sft_inscription_ids.iter().copied() {
             let operation = result
                 .wallet
                 .get_operation_by_inscription_id(inscription_id)?;
@@ -339,22 +438,16 @@ impl Inscriber {
 
         let mut merged_sft = sft_to_merge[0].clone();
         for sft in sft_to_merge.iter().skip(1) {
-            ensure!(
-                merged_sft.tick == sft.tick,
-                "All SFTs must have the same tick to be merged"
-            );
-            ensure!(
-                merged_sft.attributes == sft.attributes,
-                "All SFTs must have the same attributes to be merged"
-            );
-            ensure!(
-                merged_sft.content == sft.content,
-                "All SFTs must have the same content to be merged"
-            );
-            merged_sft.amount += sft.amount;
+            merged_sft.merge(sft.clone())?;
         }
+        merged_sft.content = merged_sft
+            .content
+            .map(|content| content.delegate_to(sft_inscription_ids[0]));
 
-        let merge_record = MergeRecord { sft: merged_sft };
+        let merge_record = MergeRecord {
+            sft: merged_sft,
+            original: sft_inscription_ids,
+        };
         result = result.with_operation(Operation::Merge(merge_record));
 
         Ok(result)
@@ -365,8 +458,21 @@ impl Inscriber {
         self
     }
 
+    /// Inscribe the inscriptions as children of the parent, the reveal transaction spends
+    /// the parent inscription and returns it to the wallet, as ord does.
+    pub fn with_parent(mut self, parent: InscriptionId) -> Self {
+        self.parent = Some(parent);
+        for inscription in self.inscriptions.iter_mut() {
+            inscription.parents = vec![inscription::inscription_id_to_bytes(parent)];
+        }
+        self
+    }
+
     fn with_operation(mut self, operation: Operation) -> Self {
-        let inscription = operation.to_inscription();
+        let mut inscription = operation.to_inscription();
+        if let Some(parent) = self.parent {
+            inscription.parents = vec![inscription::inscription_id_to_bytes(parent)];
+        }
         self.inscriptions.push(inscription);
         self
     }
@@ -618,7 +724,36 @@ impl Inscriber {
         })
     }
 
-    fn build_commit(&self, ctx: &mut InscribeContext) -> Result<()> {
+    /// Point each inscription to the first sat of its postage output. Without the pointers the
+    /// sats of the dust commit outputs would put all the inscriptions on the first postage output.
+    /// The pointers are part of the reveal scripts, so they are set before the commit is built.
+    fn point_to_outputs(&self, ctx: &InscribeContext) -> Result<Vec<Inscription>> {
+        let mut output_start = 0;
+        for inscription_id in self.inscriptions_to_burn.iter().chain(self.parent.iter()) {
+            let satpoint = self.wallet.get_inscription_satpoint_v2(*inscription_id)?;
+            output_start += ctx
+                .utxos
+                .get(&satpoint.outpoint)
+                .ok_or_else(|| anyhow!("The inscription {} is not in the wallet", inscription_id))?
+                .value;
+        }
+
+        let postage = self.option.postage().to_sat();
+        Ok(self
+            .inscriptions
+            .iter()
+            .enumerate()
+            .map(|(index, inscription)| {
+                let mut inscription = inscription.clone();
+                inscription.pointer = Some(inscription::pointer_to_bytes(
+                    output_start + postage * index as u64,
+                ));
+                inscription
+            })
+            .collect())
+    }
+
+    fn build_commit(&self, ctx: &mut InscribeContext, inscriptions: &[Inscription]) -> Result<()> {
         let secp256k1 = Secp256k1::new();
 
         // set satpoint
@@ -631,7 +766,7 @@ impl Inscriber {
 
         let dust_threshold = self.destination.script_pubkey().dust_value().to_sat();
 
-        for inscription in &self.inscriptions {
+        for inscription in inscriptions {
             let (key_pair, reveal_script, control_block, taproot_spend_info) =
                 Self::create_reveal_script_and_control_block(inscription, &secp256k1)?;
 
@@ -680,19 +815,35 @@ impl Inscriber {
         }
 
         if self.inscriptions_to_burn.len() > 0 {
-            let msg = b"bitseed".to_vec();
-            let msg_push_bytes =
-                script::PushBytesBuf::try_from(msg.clone()).expect("burn message should fit");
-
-            let script = ScriptBuf::new_op_return(&msg_push_bytes);
             let output = TxOut {
-                script_pubkey: script,
+                script_pubkey: burn_script(),
                 value: total_burn_postage,
             };
             ctx.reveal_tx.output.push(output);
             ctx.total_burn_postage = Some(total_burn_postage as f64);
         }
 
+        // Spend the parent inscription and return it before the inscription outputs,
+        // the inscriptions point to the outputs after it.
+        if let Some(parent) = self.parent {
+            let satpoint = self.wallet.get_inscription_satpoint_v2(parent)?;
+            let parent_output = ctx
+                .utxos
+                .get(&satpoint.outpoint)
+                .ok_or_else(|| anyhow!("The parent inscription {} is not in the wallet", parent))?
+                .clone();
+            ctx.reveal_tx.input.push(TxIn {
+                previous_output: satpoint.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            });
+            ctx.reveal_tx.output.push(TxOut {
+                script_pubkey: self.wallet.get_change_address()?.script_pubkey(),
+                value: parent_output.value,
+            });
+        }
+
         // Process the logic of inscription revelation
         let commit_input_start_index = ctx.reveal_tx.input.len();
 
@@ -1004,8 +1155,9 @@ impl Inscriber {
 
     pub fn inscribe(&self) -> Result<InscribeOutput> {
         let mut ctx = self.prepare_context()?;
+        let inscriptions = self.point_to_outputs(&ctx)?;
 
-        self.build_commit(&mut ctx)?;
+        self.build_commit(&mut ctx, &inscriptions)?;
         self.build_revert(&mut ctx)?;
         self.update_fees(&mut ctx)?;
         self.sign(&mut ctx)?;
@@ -1,9 +1,12 @@
+use crate::error::{BitseedError, BitseedResult};
 use crate::{
-    sft::Content, METADATA_AMOUNT, METADATA_ATTRIBUTES, METADATA_OP, METADATA_TICK, PROTOCOL,
+    cbor, sft::Content, METADATA_AMOUNT, METADATA_ATTRIBUTES, METADATA_OP, METADATA_ORIGINAL,
+    METADATA_TICK, METADATA_USER_INPUT, PROTOCOL,
 };
-use anyhow::{anyhow, ensure, Result};
+use bitcoin::{hashes::Hash, Txid};
 use ciborium::{value::Integer, Value};
-use ord::Inscription;
+use ord::{Inscription, InscriptionId};
+use std::str::FromStr;
 
 pub struct InscriptionBuilder {
     inscription: Inscription,
@@ -41,8 +44,40 @@ impl InscriptionBuilder {
         self
     }
 
+    pub fn user_input(mut self, user_input: String) -> Self {
+        self.metadata = self.metadata.add_string(METADATA_USER_INPUT, user_input);
+        self
+    }
+
+    /// The source SFT inscription ids of a split or merge, an empty original is not written.
+    pub fn original(mut self, original: Vec<InscriptionId>) -> Self {
+        if original.is_empty() {
+            return self;
+        }
+        let original = original
+            .iter()
+            .map(|inscription_id| Value::Text(inscription_id.to_string()))
+            .collect();
+        self.metadata = self.metadata.add(METADATA_ORIGINAL, Value::Array(original));
+        self
+    }
+
+    /// Inscribe as a child of the parent inscription, the reveal transaction must spend the parent.
+    pub fn parent(mut self, parent: InscriptionId) -> Self {
+        self.inscription.parents = vec![inscription_id_to_bytes(parent)];
+        self
+    }
+
+    /// The delegated content is inscribed as an ord delegate without the body.
     pub fn content(mut self, content: Content) -> Self {
+        if let Some(delegate) = content.delegate_id() {
+            self.inscription.delegate = Some(inscription_id_to_bytes(delegate));
+            return self;
+        }
         self.inscription.content_type = Some(content.content_type.into_bytes());
+        self.inscription.content_encoding = content
+            .content_encoding
+            .map(|content_encoding| content_encoding.into_bytes());
         self.inscription.body = Some(content.body);
         self
     }
@@ -90,8 +125,10 @@ impl MetadataBuilder {
         self.add(key, Value::Bool(value))
     }
 
+    /// The metadata with the keys sorted in the canonical order. The values are kept as they
+    /// are, the attributes stay in the order of the generator, see `metadata::abi_attributes`.
     pub fn finish(self) -> Value {
-        self.metadata
+        cbor::sort_keys(self.metadata)
     }
 
     pub fn finish_to_bytes(self) -> Vec<u8> {
@@ -107,23 +144,29 @@ pub struct BitseedInscription {
 }
 
 impl BitseedInscription {
-    pub fn new(inscription: Inscription) -> Result<Self> {
+    pub fn new(inscription: Inscription) -> BitseedResult<Self> {
         let metaprotocol = inscription
             .metaprotocol()
-            .ok_or_else(|| anyhow!("metaprotocol not found"))?;
-        ensure!(metaprotocol == PROTOCOL, "metaprotocol is not bitseed");
+            .ok_or_else(|| BitseedError::NotBitseed("metaprotocol not found".to_string()))?;
+        if metaprotocol != PROTOCOL {
+            return Err(BitseedError::NotBitseed(format!(
+                "metaprotocol is {}",
+                metaprotocol
+            )));
+        }
         Ok(Self { inscription })
     }
 
-    pub fn get_metadata(&self) -> Result<Value> {
+    pub fn get_metadata(&self) -> BitseedResult<Value> {
         self.inscription
             .metadata()
-            .ok_or_else(|| anyhow!("metadata not found"))
+            .ok_or(BitseedError::MissingMetadata)
     }
 
-    pub fn get_metadata_value(&self, key: &str) -> Result<Value> {
+    pub fn get_metadata_value(&self, key: &str) -> BitseedResult<Value> {
+        self.get_metadata()?;
         self.get_metadata_value_opt(key)
-            .ok_or_else(|| anyhow!("key ({:?}) not found in metadata", key))
+            .ok_or_else(|| BitseedError::MissingField(key.to_string()))
     }
 
     pub fn get_metadata_value_opt(&self, key: &str) -> Option<Value> {
@@ -134,41 +177,66 @@ impl BitseedInscription {
             .map(|(_, v)| v.clone())
     }
 
-    pub fn get_metadata_string(&self, key: &str) -> Result<String> {
+    pub fn get_metadata_string(&self, key: &str) -> BitseedResult<String> {
         self.get_metadata_value(key)?
             .as_text()
             .map(|v| v.to_string())
-            .ok_or_else(|| anyhow!("{} is not a string", key))
+            .ok_or_else(|| BitseedError::invalid_field(key, "not a string"))
     }
 
-    pub fn get_metadata_u64(&self, key: &str) -> Result<u64> {
+    pub fn get_metadata_u64(&self, key: &str) -> BitseedResult<u64> {
         let i = self
             .get_metadata_value(key)?
             .as_integer()
-            .ok_or_else(|| anyhow!("{} is not an integer", key))?;
-        u64::try_from(i).map_err(|_| anyhow!("{} is not a u64", key))
+            .ok_or_else(|| BitseedError::invalid_field(key, "not an integer"))?;
+        u64::try_from(i).map_err(|_| BitseedError::invalid_field(key, "not a u64"))
     }
 
-    pub fn op(&self) -> Result<String> {
+    pub fn op(&self) -> BitseedResult<String> {
         self.get_metadata_string(METADATA_OP)
     }
 
-    pub fn tick(&self) -> Result<String> {
+    pub fn tick(&self) -> BitseedResult<String> {
         self.get_metadata_string(METADATA_TICK)
     }
 
-    pub fn amount(&self) -> Result<u64> {
-        let amount = self
-            .get_metadata_value(METADATA_AMOUNT)?
-            .as_integer()
-            .ok_or_else(|| anyhow!("amount is not an integer"))?;
-        u64::try_from(amount).map_err(|_| anyhow!("amount is not a u64"))
+    pub fn amount(&self) -> BitseedResult<u64> {
+        self.get_metadata_u64(METADATA_AMOUNT)
     }
 
     pub fn attributes(&self) -> Option<Value> {
         self.get_metadata_value_opt(METADATA_ATTRIBUTES)
     }
 
+    pub fn user_input(&self) -> Option<String> {
+        self.get_metadata_value_opt(METADATA_USER_INPUT)
+            .and_then(|v| v.as_text().map(|txt| txt.to_owned()))
+    }
+
+    /// The source SFT inscription ids of a split or merge, empty if the metadata does not have it.
+    pub fn original(&self) -> BitseedResult<Vec<InscriptionId>> {
+        let original = match self.get_metadata_value_opt(METADATA_ORIGINAL) {
+            Some(original) => original,
+            None => return Ok(vec![]),
+        };
+        original
+            .as_array()
+            .ok_or_else(|| BitseedError::invalid_field(METADATA_ORIGINAL, "not an array"))?
+            .iter()
+            .map(|v| {
+                let text = v.as_text().ok_or_else(|| {
+                    BitseedError::invalid_field(METADATA_ORIGINAL, "inscription id is not a string")
+                })?;
+                InscriptionId::from_str(text).map_err(|e| {
+                    BitseedError::invalid_field(
+                        METADATA_ORIGINAL,
+                        format!("invalid inscription id {}: {}", text, e),
+                    )
+                })
+            })
+            .collect()
+    }
+
     pub fn get_attribute(&self, key: &str) -> Option<Value> {
         self.attributes().and_then(|attributes| {
             attributes.as_map().and_then(|map| {
@@ -179,13 +247,188 @@ impl BitseedInscription {
         })
     }
 
+    /// The content of the inscription, the delegated content is not resolved.
     pub fn content(&self) -> Option<Content> {
-        let content_type = self.inscription.content_type();
-        let body = self.inscription.body();
-        if let (Some(content_type), Some(body)) = (content_type, body) {
-            Some(Content::new(content_type.to_owned(), body.to_vec()))
-        } else {
-            None
+        content(&self.inscription)
+    }
+}
+
+/// The content of any inscription, the delegated content is not resolved.
+pub fn content(inscription: &Inscription) -> Option<Content> {
+    if let Some(delegate) = &inscription.delegate {
+        return inscription_id_from_bytes(delegate).map(Content::delegated);
+    }
+    let content_type = inscription.content_type();
+    let body = inscription.body();
+    if let (Some(content_type), Some(body)) = (content_type, body) {
+        let mut content = Content::new(content_type.to_owned(), body.to_vec());
+        content.content_encoding = inscription
+            .content_encoding
+            .as_ref()
+            .map(|content_encoding| String::from_utf8_lossy(content_encoding).into_owned());
+        Some(content)
+    } else {
+        None
+    }
+}
+
+/// Encode the inscription id as the ord parent tag value: the txid bytes followed by
+/// the little endian index with the trailing zeros stripped.
+pub fn inscription_id_to_bytes(inscription_id: InscriptionId) -> Vec<u8> {
+    let index = inscription_id.index.to_le_bytes();
+    let mut index = index.as_slice();
+    while index.last() == Some(&0) {
+        index = &index[..index.len() - 1];
+    }
+    inscription_id
+        .txid
+        .to_byte_array()
+        .iter()
+        .chain(index)
+        .copied()
+        .collect()
+}
+
+/// Decode the ord parent tag value, returns None if it is not in the canonical form.
+pub fn inscription_id_from_bytes(bytes: &[u8]) -> Option<InscriptionId> {
+    if bytes.len() < 32 || bytes.len() > 36 || (bytes.len() > 32 && bytes.last() == Some(&0)) {
+        return None;
+    }
+    let txid = Txid::from_slice(&bytes[..32]).ok()?;
+    let mut index = [0u8; 4];
+    index[..bytes.len() - 32].copy_from_slice(&bytes[32..]);
+    Some(InscriptionId {
+        txid,
+        index: u32::from_le_bytes(index),
+    })
+}
+
+/// The parent inscription ids of the inscription, the invalid ones are ignored.
+pub fn parents(inscription: &Inscription) -> Vec<InscriptionId> {
+    inscription
+        .parents
+        .iter()
+        .filter_map(|parent| inscription_id_from_bytes(parent))
+        .collect()
+}
+
+/// Encode the ord pointer tag value: the little endian offset with the trailing zeros stripped.
+pub fn pointer_to_bytes(pointer: u64) -> Vec<u8> {
+    let mut bytes = pointer.to_le_bytes().to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    bytes
+}
+
+/// The sat offset in the outputs which the inscription points to, decoded as ord does.
+pub fn pointer(inscription: &Inscription) -> Option<u64> {
+    let bytes = inscription.pointer.as_ref()?;
+    if bytes.iter().skip(8).any(|byte| *byte != 0) {
+        return None;
+    }
+    let mut pointer = [0u8; 8];
+    let len = bytes.len().min(8);
+    pointer[..len].copy_from_slice(&bytes[..len]);
+    Some(u64::from_le_bytes(pointer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sft::Delegate;
+
+    #[test]
+    fn test_inscription_id_bytes() {
+        let txid =
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b")
+                .unwrap();
+        for (index, len) in [(0, 32), (1, 33), (256, 34), (u32::MAX, 36)] {
+            let inscription_id = InscriptionId { txid, index };
+            let bytes = inscription_id_to_bytes(inscription_id);
+            assert_eq!(bytes.len(), len);
+            assert_eq!(inscription_id_from_bytes(&bytes), Some(inscription_id));
         }
+
+        let mut bytes = inscription_id_to_bytes(InscriptionId { txid, index: 1 });
+        bytes.push(0);
+        assert_eq!(inscription_id_from_bytes(&bytes), None);
+        assert_eq!(inscription_id_from_bytes(&bytes[..31]), None);
+
+        let inscription = InscriptionBuilder::new()
+            .op("mint".to_string())
+            .parent(InscriptionId { txid, index: 2 })
+            .finish();
+        assert_eq!(
+            parents(&inscription),
+            vec![InscriptionId { txid, index: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_pointer_bytes() {
+        for (offset, len) in [(0, 0), (1, 1), (10_546, 2), (u64::MAX, 8)] {
+            let inscription = Inscription {
+                pointer: Some(pointer_to_bytes(offset)),
+                ..Default::default()
+            };
+            assert_eq!(inscription.pointer.as_ref().unwrap().len(), len);
+            assert_eq!(pointer(&inscription), Some(offset));
+        }
+
+        let mut inscription = Inscription::default();
+        assert_eq!(pointer(&inscription), None);
+        inscription.pointer = Some(vec![1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(pointer(&inscription), Some(1));
+        inscription.pointer = Some(vec![1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(pointer(&inscription), None);
+    }
+
+    #[test]
+    fn test_delegated_content() {
+        let txid =
+            Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b")
+                .unwrap();
+        let original_id = InscriptionId { txid, index: 0 };
+        let original = Content::text("hello".to_string());
+        let delegated = original.delegate_to(original_id);
+
+        let inscription = InscriptionBuilder::new()
+            .op("split".to_string())
+            .content(delegated.clone())
+            .finish();
+        assert!(inscription.body.is_none());
+
+        let mut content = BitseedInscription::new(inscription)
+            .unwrap()
+            .content()
+            .unwrap();
+        assert_eq!(content, Content::delegated(original_id));
+        assert_ne!(content, original);
+        assert_ne!(
+            content,
+            Content::delegated(InscriptionId { txid, index: 1 })
+        );
+
+        content.resolve(Some(original.clone())).unwrap();
+        assert_eq!(content, original);
+        assert_eq!(content, delegated);
+        assert_eq!(
+            content
+                .delegate_to(InscriptionId { txid, index: 1 })
+                .delegate,
+            Some(Delegate::Resolved(original_id))
+        );
+
+        // The delegates are not chained.
+        let mut chained = Content::delegated(InscriptionId { txid, index: 1 });
+        assert!(!chained.is_resolved());
+        assert!(chained.resolve(Some(delegated)).is_err());
+
+        // An empty content type is a resolved content, it is not taken as unresolved.
+        let mut empty = Content::new(String::new(), vec![]).delegate_to(original_id);
+        assert!(empty.is_resolved());
+        empty.resolve(Some(original.clone())).unwrap();
+        assert_eq!(empty, Content::new(String::new(), vec![]));
     }
 }
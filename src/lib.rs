@@ -8,14 +8,24 @@ pub const METADATA_OP: &str = "op";
 pub const METADATA_TICK: &str = "tick";
 pub const METADATA_AMOUNT: &str = "amount";
 pub const METADATA_ATTRIBUTES: &str = "attributes";
+pub const METADATA_USER_INPUT: &str = "user_input";
+pub const METADATA_ORIGINAL: &str = "original";
 pub const GENERATOR_TICK: &str = "generator";
 
+pub mod cbor;
 pub mod commands;
+pub mod compression;
+pub mod deploy_args;
+pub mod error;
 pub mod generator;
+pub mod indexer;
 pub mod inscribe;
 pub mod inscription;
 pub mod operation;
+pub mod server;
 pub mod sft;
+pub mod store;
+pub mod tick;
 mod wallet;
 
 #[derive(Parser)]
@@ -55,6 +65,9 @@ pub enum Commands {
     Split(commands::split::SplitCommand),
     Merge(commands::merge::MergeCommand),
     View(commands::view::ViewCommand),
+    List(commands::list::ListCommand),
+    Reindex(commands::reindex::ReindexCommand),
+    Server(commands::server::ServerCommand),
 }
 
 pub fn run(cli: BitseedCli) -> SubcommandResult {
@@ -66,6 +79,9 @@ pub fn run(cli: BitseedCli) -> SubcommandResult {
         Commands::Split(split) => split.run(wallet),
         Commands::Merge(merge) => merge.run(wallet),
         Commands::View(view) => view.run(wallet),
+        Commands::List(list) => list.run(wallet),
+        Commands::Reindex(reindex) => reindex.run(wallet),
+        Commands::Server(server) => server.run(wallet),
     }?;
 
     Ok(output)
@@ -1,15 +1,16 @@
 use crate::{
+    deploy_args::DeployArgs,
+    error::{BitseedError, BitseedResult},
     inscription::{BitseedInscription, InscriptionBuilder},
-    sft::SFT,
+    sft::{Content, SFT},
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, ensure, Result};
 use ciborium::Value;
-use ord::Inscription;
+use ord::{Inscription, InscriptionId};
 use serde::{Deserialize, Serialize};
 
-use serde_json;
-use serde_json::Value as JSONValue;
 use std::io::Cursor;
+use std::str::FromStr;
 
 pub trait AsSFT {
     fn as_sft(&self) -> SFT;
@@ -23,6 +24,9 @@ pub struct DeployRecord {
     pub generator: Option<String>,
     pub factory: Option<String>,
     pub repeat: u64,
+    // Whether the mint requires the user input
+    #[serde(default)]
+    pub has_user_input: bool,
     pub deploy_args: Vec<u8>,
 }
 
@@ -33,6 +37,7 @@ impl DeployRecord {
         generator: Option<String>,
         factory: Option<String>,
         repeat: u64,
+        has_user_input: bool,
         deploy_args: Vec<u8>,
     ) -> Self {
         Self {
@@ -41,14 +46,66 @@ impl DeployRecord {
             generator,
             factory,
             repeat,
+            has_user_input,
             deploy_args,
         }
     }
+
+    /// The inscription id of the generator, the generator is referenced as "/inscription/<id>".
+    pub fn generator_inscription_id(&self) -> Option<InscriptionId> {
+        self.generator
+            .as_deref()
+            .and_then(|generator| generator.strip_prefix("/inscription/"))
+            .and_then(|inscription_id| InscriptionId::from_str(inscription_id).ok())
+    }
+
+    /// Decode the deploy args with the DeployArgs schema.
+    pub fn decode_deploy_args(&self) -> BitseedResult<DeployArgs> {
+        DeployArgs::from_cbor(&self.deploy_args)
+    }
+
+    /// Check that the user input is given if and only if the deploy requires it.
+    pub fn check_user_input(&self, user_input: Option<&str>) -> Result<()> {
+        match (self.has_user_input, user_input) {
+            (true, None) => bail!("The tick {} requires the user input to mint", self.tick),
+            (false, Some(_)) => bail!("The tick {} does not accept the user input", self.tick),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that minting `amount` on top of the `minted` supply does not exceed the total supply.
+    pub fn check_supply(&self, minted: u64, amount: u64) -> Result<()> {
+        let remaining = self.amount.saturating_sub(minted);
+        ensure!(
+            amount <= remaining,
+            "The mint amount {} exceeds the remaining supply {} of tick {}",
+            amount,
+            remaining,
+            self.tick
+        );
+        Ok(())
+    }
+
+    /// Check that one more SFT can be minted with the same attributes. `repeat` is the maximum
+    /// number of mints with the same attributes, so 1 makes the attributes unique and 0 means
+    /// no limit. `repeated` is the number of existing SFTs minted with the same attributes.
+    pub fn check_repeat(&self, repeated: u64) -> Result<()> {
+        ensure!(
+            self.repeat == 0 || repeated < self.repeat,
+            "The generated attributes have been minted {} times, reaches the repeat limit {} of tick {}",
+            repeated,
+            self.repeat,
+            self.tick
+        );
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MintRecord {
     pub sft: SFT,
+    // The user input passed to the generator, it is required to verify the mint.
+    pub user_input: Option<String>,
 }
 
 impl AsSFT for MintRecord {
@@ -60,6 +117,9 @@ impl AsSFT for MintRecord {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SplitRecord {
     pub sft: SFT,
+    // The inscription id of the SFT which is split
+    #[serde(default)]
+    pub original: Vec<InscriptionId>,
 }
 
 impl AsSFT for SplitRecord {
@@ -71,6 +131,9 @@ impl AsSFT for SplitRecord {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MergeRecord {
     pub sft: SFT,
+    // The inscription ids of the SFTs which are merged
+    #[serde(default)]
+    pub original: Vec<InscriptionId>,
 }
 
 impl AsSFT for MergeRecord {
@@ -109,6 +172,11 @@ impl Operation {
                     Value::Integer(record.repeat.into()),
                 ));
 
+                att_values.push((
+                    Value::Text("has_user_input".to_string()),
+                    Value::Bool(record.has_user_input),
+                ));
+
                 att_values.push((Value::Text("deploy_args".to_string()), cbor_value));
 
                 let attributes = ciborium::Value::Map(att_values);
@@ -127,6 +195,9 @@ impl Operation {
                 if let Some(attributes) = record.sft.attributes {
                     builder = builder.attributes(attributes);
                 }
+                if let Some(user_input) = record.user_input {
+                    builder = builder.user_input(user_input);
+                }
                 if let Some(content) = record.sft.content {
                     builder = builder.content(content)
                 }
@@ -136,7 +207,8 @@ impl Operation {
                 let mut builder = InscriptionBuilder::new()
                     .op(op.clone())
                     .tick(record.sft.tick.clone())
-                    .amount(record.sft.amount);
+                    .amount(record.sft.amount)
+                    .original(record.original);
                 if let Some(attributes) = record.sft.attributes {
                     builder = builder.attributes(attributes);
                 }
@@ -149,7 +221,8 @@ impl Operation {
                 let mut builder = InscriptionBuilder::new()
                     .op(op.clone())
                     .tick(record.sft.tick.clone())
-                    .amount(record.sft.amount);
+                    .amount(record.sft.amount)
+                    .original(record.original);
                 if let Some(attributes) = record.sft.attributes {
                     builder = builder.attributes(attributes);
                 }
@@ -161,9 +234,12 @@ impl Operation {
         }
     }
 
-    pub fn from_inscription(inscription: Inscription) -> Result<Self> {
+    pub fn from_inscription(inscription: Inscription) -> BitseedResult<Self> {
         let bitseed_inscription = BitseedInscription::new(inscription)?;
         let op = bitseed_inscription.op()?;
+        if !matches!(op.as_str(), "deploy" | "mint" | "split" | "merge") {
+            return Err(BitseedError::UnsupportedOp(op));
+        }
         let tick = bitseed_inscription.tick()?;
         let amount = bitseed_inscription.amount()?;
         let content = bitseed_inscription.content();
@@ -179,23 +255,37 @@ impl Operation {
                     .map(|v| v.as_text().map(|txt| txt.to_owned()))
                     .flatten();
                 match (&generator, &factory) {
-                    (Some(_), Some(_)) => bail!("generator and factory are mutually exclusive"),
-                    (None, None) => bail!("missing generator or factory"),
+                    (Some(_), Some(_)) => {
+                        return Err(BitseedError::invalid_field(
+                            "generator",
+                            "generator and factory are mutually exclusive",
+                        ))
+                    }
+                    (None, None) => {
+                        return Err(BitseedError::MissingField("generator".to_string()))
+                    }
                     _ => {}
                 }
                 let repeat = bitseed_inscription
                     .get_attribute("repeat")
-                    .ok_or_else(|| anyhow!("missing repeat"))?
+                    .ok_or_else(|| BitseedError::MissingField("repeat".to_string()))?
                     .as_integer()
-                    .ok_or_else(|| anyhow!("repeat is not an integer"))?
-                    .try_into()?;
+                    .ok_or_else(|| BitseedError::invalid_field("repeat", "not an integer"))?
+                    .try_into()
+                    .map_err(|_| BitseedError::invalid_field("repeat", "not a u64"))?;
+                let has_user_input = match bitseed_inscription.get_attribute("has_user_input") {
+                    Some(value) => value.as_bool().ok_or_else(|| {
+                        BitseedError::invalid_field("has_user_input", "not a bool")
+                    })?,
+                    None => false,
+                };
                 let deploy_args_value = bitseed_inscription
                     .get_attribute("deploy_args")
-                    .ok_or_else(|| anyhow!("missing deploy_args"))?;
+                    .ok_or_else(|| BitseedError::MissingField("deploy_args".to_string()))?;
 
                 let mut deploy_args = Vec::new();
                 ciborium::into_writer(&deploy_args_value, &mut deploy_args)
-                    .expect("ciborium marshal failed");
+                    .map_err(|e| BitseedError::InvalidDeployArgs(e.to_string()))?;
 
                 Ok(Operation::Deploy(DeployRecord::new_deploy_record(
                     tick,
@@ -203,6 +293,7 @@ impl Operation {
                     generator,
                     factory,
                     repeat,
+                    has_user_input,
                     deploy_args,
                 )))
             }
@@ -216,17 +307,24 @@ impl Operation {
                 };
 
                 let op = match op.as_ref() {
-                    "mint" => Operation::Mint(MintRecord { sft }),
-                    "split" => Operation::Split(SplitRecord { sft }),
-                    "merge" => Operation::Merge(MergeRecord { sft }),
+                    "mint" => Operation::Mint(MintRecord {
+                        sft,
+                        user_input: bitseed_inscription.user_input(),
+                    }),
+                    "split" => Operation::Split(SplitRecord {
+                        sft,
+                        original: bitseed_inscription.original()?,
+                    }),
+                    "merge" => Operation::Merge(MergeRecord {
+                        sft,
+                        original: bitseed_inscription.original()?,
+                    }),
                     _ => unreachable!(), // We already know it's one of the three.
                 };
 
                 Ok(op)
             }
-            _ => {
-                bail!("unknown op: {}", op)
-            }
+            _ => Err(BitseedError::UnsupportedOp(op)),
         }
     }
 
@@ -274,6 +372,37 @@ impl Operation {
         }
     }
 
+    /// The SFT content, None for a deploy.
+    pub fn content(&self) -> Option<&Content> {
+        match self {
+            Operation::Deploy(_) => None,
+            Operation::Mint(record) => record.sft.content.as_ref(),
+            Operation::Split(record) => record.sft.content.as_ref(),
+            Operation::Merge(record) => record.sft.content.as_ref(),
+        }
+    }
+
+    /// The delegate inscription of the SFT content if the content is not resolved yet.
+    pub fn unresolved_delegate(&self) -> Option<InscriptionId> {
+        self.content()
+            .filter(|content| !content.is_resolved())
+            .and_then(|content| content.delegate_id())
+    }
+
+    /// Resolve the delegated SFT content with the content of the delegate inscription.
+    pub fn resolve_content(mut self, delegate_content: Option<Content>) -> Result<Self> {
+        let content = match &mut self {
+            Operation::Deploy(_) => None,
+            Operation::Mint(record) => record.sft.content.as_mut(),
+            Operation::Split(record) => record.sft.content.as_mut(),
+            Operation::Merge(record) => record.sft.content.as_mut(),
+        };
+        if let Some(content) = content {
+            content.resolve(delegate_content)?;
+        }
+        Ok(self)
+    }
+
     pub fn op(&self) -> String {
         match self {
             Operation::Deploy(_) => "deploy".to_string(),
@@ -284,17 +413,126 @@ impl Operation {
     }
 }
 
-pub fn deploy_args_cbor_encode(deploy_args: Vec<String>) -> Vec<u8> {
-    let mut mint_args_json: Vec<JSONValue> = vec![];
+/// Validate the JSON deploy args against the DeployArgs schema and encode them to CBOR.
+pub fn deploy_args_cbor_encode(deploy_args: Vec<String>) -> BitseedResult<Vec<u8>> {
+    DeployArgs::from_json_strings(&deploy_args)?.to_cbor()
+}
 
-    for arg in deploy_args.iter() {
-        let arg_json: JSONValue =
-            serde_json::from_str(arg.as_str()).expect("serde_json unmarshal failed");
-        mint_args_json.push(arg_json);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::METADATA_ORIGINAL;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_from_inscription_errors() {
+        let not_bitseed = Operation::from_inscription(Inscription::default()).unwrap_err();
+        assert!(matches!(not_bitseed, BitseedError::NotBitseed(_)));
+        assert!(!not_bitseed.is_malformed());
+
+        let unsupported = InscriptionBuilder::new()
+            .op("transfer".to_string())
+            .tick("BITS")
+            .amount(1000)
+            .finish();
+        assert_eq!(
+            Operation::from_inscription(unsupported).unwrap_err(),
+            BitseedError::UnsupportedOp("transfer".to_string())
+        );
+
+        let missing_amount = InscriptionBuilder::new()
+            .op("mint".to_string())
+            .tick("BITS")
+            .finish();
+        let err = Operation::from_inscription(missing_amount).unwrap_err();
+        assert_eq!(err, BitseedError::MissingField("amount".to_string()));
+        assert!(err.is_malformed());
+
+        let mint = InscriptionBuilder::new()
+            .op("mint".to_string())
+            .tick("BITS")
+            .amount(1000)
+            .finish();
+        assert!(Operation::from_inscription(mint).unwrap().is_mint());
     }
 
-    let mint_args_array = JSONValue::Array(mint_args_json);
-    let mut cbor_buffer = Vec::new();
-    ciborium::into_writer(&mint_args_array, &mut cbor_buffer).expect("ciborium marshal failed");
-    cbor_buffer
+    #[test]
+    fn test_original_round_trip() {
+        let original = (0..2)
+            .map(|index| InscriptionId {
+                txid: bitcoin::Txid::all_zeros(),
+                index,
+            })
+            .collect::<Vec<_>>();
+        let sft = SFT::new("BITS".to_string(), 1000, None, None);
+
+        let split = Operation::Split(SplitRecord {
+            sft: sft.clone(),
+            original: original[..1].to_vec(),
+        });
+        assert_eq!(
+            Operation::from_inscription(split.clone().to_inscription()).unwrap(),
+            split
+        );
+
+        let merge = Operation::Merge(MergeRecord {
+            sft: sft.clone(),
+            original: original.clone(),
+        });
+        assert_eq!(
+            Operation::from_inscription(merge.clone().to_inscription()).unwrap(),
+            merge
+        );
+
+        // An empty original is not written, the indexer rejects the transfer without it.
+        let inscription = Operation::Split(SplitRecord {
+            sft,
+            original: vec![],
+        })
+        .to_inscription();
+        let bitseed_inscription = BitseedInscription::new(inscription).unwrap();
+        assert!(bitseed_inscription
+            .get_metadata_value_opt(METADATA_ORIGINAL)
+            .is_none());
+        assert_eq!(bitseed_inscription.original().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_check_repeat() {
+        let deploy = |repeat| {
+            DeployRecord::new_deploy_record(
+                "BITS".to_string(),
+                1000,
+                None,
+                None,
+                repeat,
+                false,
+                vec![],
+            )
+        };
+
+        assert!(deploy(0).check_repeat(0).is_ok());
+        assert!(deploy(0).check_repeat(u64::MAX).is_ok());
+
+        assert!(deploy(1).check_repeat(0).is_ok());
+        assert!(deploy(1).check_repeat(1).is_err());
+
+        assert!(deploy(2).check_repeat(1).is_ok());
+        assert!(deploy(2).check_repeat(2).is_err());
+    }
+
+    #[test]
+    fn test_deploy_args_cbor_encode() {
+        assert!(deploy_args_cbor_encode(vec![
+            r#"{"height":{"type":"range","data":{"min":1,"max":1000}}}"#.to_string()
+        ])
+        .is_ok());
+        assert!(
+            deploy_args_cbor_encode(vec![r#"{"height":{"type":"range"}}"#.to_string()]).is_err()
+        );
+        assert!(matches!(
+            deploy_args_cbor_encode(vec!["{height".to_string()]),
+            Err(BitseedError::InvalidDeployArgs(_))
+        ));
+    }
 }
@@ -0,0 +1,279 @@
+use crate::indexer::{Indexer, IndexerState, SFTEntry};
+use crate::operation::DeployRecord;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use ord::InscriptionId;
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// The state shared by the HTTP handlers, the indexer updates it in place.
+pub struct ServerState {
+    state: RwLock<IndexerState>,
+}
+
+impl ServerState {
+    pub fn new(state: IndexerState) -> Self {
+        Self {
+            state: RwLock::new(state),
+        }
+    }
+
+    /// Index the new blocks on the served state.
+    pub fn index(&self, indexer: &mut Indexer) -> anyhow::Result<()> {
+        indexer.index_to_tip_shared(&self.state)
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&IndexerState) -> T) -> T {
+        f(&self
+            .state
+            .read()
+            .expect("the server state lock is poisoned"))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeployResponse {
+    pub inscription_id: InscriptionId,
+    pub deploy: DeployRecord,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupplyResponse {
+    pub tick: String,
+    pub supply: u64,
+    pub minted: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LineageResponse {
+    pub inscription_id: InscriptionId,
+    // The SFTs which are burned to create the SFT by split or merge, empty for a mint
+    pub sources: Vec<InscriptionId>,
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    BadRequest(String),
+    NotFound(String),
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        match self {
+            ServerError::BadRequest(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+            ServerError::NotFound(message) => (StatusCode::NOT_FOUND, message).into_response(),
+        }
+    }
+}
+
+type ServerResult<T> = Result<Json<T>, ServerError>;
+
+pub fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/deploy/:tick", get(deploy))
+        .route("/supply/:tick", get(supply))
+        .route("/sft/:inscription_id", get(sft))
+        .route("/sfts/:address", get(sfts_by_owner))
+        .route("/lineage/:inscription_id", get(lineage))
+        .with_state(state)
+}
+
+fn parse_inscription_id(inscription_id: &str) -> Result<InscriptionId, ServerError> {
+    InscriptionId::from_str(inscription_id).map_err(|e| {
+        ServerError::BadRequest(format!("Invalid inscription id {}: {}", inscription_id, e))
+    })
+}
+
+async fn deploy(
+    State(server): State<Arc<ServerState>>,
+    Path(tick): Path<String>,
+) -> ServerResult<DeployResponse> {
+    server
+        .read(|state| {
+            state.get_tick(&tick).map(|entry| DeployResponse {
+                inscription_id: entry.deploy_inscription_id,
+                deploy: entry.deploy.clone(),
+            })
+        })
+        .map(Json)
+        .ok_or_else(|| ServerError::NotFound(format!("tick {} is not deployed", tick)))
+}
+
+async fn supply(
+    State(server): State<Arc<ServerState>>,
+    Path(tick): Path<String>,
+) -> ServerResult<SupplyResponse> {
+    server
+        .read(|state| {
+            state.get_tick(&tick).map(|entry| SupplyResponse {
+                tick: entry.deploy.tick.clone(),
+                supply: entry.deploy.amount,
+                minted: entry.minted,
+            })
+        })
+        .map(Json)
+        .ok_or_else(|| ServerError::NotFound(format!("tick {} is not deployed", tick)))
+}
+
+async fn sft(
+    State(server): State<Arc<ServerState>>,
+    Path(inscription_id): Path<String>,
+) -> ServerResult<SFTEntry> {
+    let inscription_id = parse_inscription_id(&inscription_id)?;
+    server
+        .read(|state| state.get_sft(&inscription_id).cloned())
+        .map(Json)
+        .ok_or_else(|| ServerError::NotFound(format!("SFT {} not found", inscription_id)))
+}
+
+async fn sfts_by_owner(
+    State(server): State<Arc<ServerState>>,
+    Path(address): Path<String>,
+) -> ServerResult<Vec<SFTEntry>> {
+    let sfts = server.read(|state| state.sfts_by_owner(&address).into_iter().cloned().collect());
+    Ok(Json(sfts))
+}
+
+async fn lineage(
+    State(server): State<Arc<ServerState>>,
+    Path(inscription_id): Path<String>,
+) -> ServerResult<LineageResponse> {
+    let inscription_id = parse_inscription_id(&inscription_id)?;
+    server
+        .read(|state| match state.get_lineage(&inscription_id) {
+            Some(sources) => Some(sources.clone()),
+            None => state.get_sft(&inscription_id).map(|_| vec![]),
+        })
+        .map(|sources| {
+            Json(LineageResponse {
+                inscription_id,
+                sources,
+            })
+        })
+        .ok_or_else(|| ServerError::NotFound(format!("SFT {} not found", inscription_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sft::SFT;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, OutPoint};
+    use ordinals::SatPoint;
+
+    fn inscription_id(index: u32) -> InscriptionId {
+        InscriptionId::from_str(&format!(
+            "6f55475ce65054aa8371d618d217da8c9a764cecdaf4debcbce8d6312fe6b4d8i{}",
+            index
+        ))
+        .unwrap()
+    }
+
+    fn sft_entry(index: u32, owner: &str) -> SFTEntry {
+        SFTEntry {
+            inscription_id: inscription_id(index),
+            sft: SFT::new("BITS".to_string(), 1000, None, None),
+            indexer_attributes: None,
+            satpoint: SatPoint {
+                outpoint: OutPoint {
+                    txid: inscription_id(index).txid,
+                    vout: 0,
+                },
+                offset: 0,
+            },
+            owner: Some(owner.to_string()),
+        }
+    }
+
+    /// Index a block with a deploy, a mint and a merged SFT.
+    fn server_state() -> (ServerState, IndexerState) {
+        let mut state = IndexerState::new();
+        state.insert_tick(
+            inscription_id(0),
+            DeployRecord::new_deploy_record(
+                "BITS".to_string(),
+                10000,
+                None,
+                Some("fixed".to_string()),
+                0,
+                false,
+                vec![],
+            ),
+            None,
+        );
+        state.add_mint("BITS", 1000, Default::default());
+        state.insert_sft(sft_entry(1, "alice"));
+        state.insert_sft(sft_entry(2, "bob"));
+        state.insert_lineage(
+            inscription_id(2),
+            vec![inscription_id(3), inscription_id(4)],
+        );
+        state.commit_block(1, BlockHash::all_zeros(), 6);
+
+        (ServerState::new(state.clone()), state)
+    }
+
+    #[tokio::test]
+    async fn test_handlers() {
+        let (server, _) = server_state();
+        let server = Arc::new(server);
+        let id = |index| inscription_id(index).to_string();
+
+        let deployed = deploy(State(server.clone()), Path("bits".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(deployed.inscription_id, inscription_id(0));
+        assert!(matches!(
+            deploy(State(server.clone()), Path("NONE".to_string())).await,
+            Err(ServerError::NotFound(_))
+        ));
+
+        let supplied = supply(State(server.clone()), Path("BITS".to_string()))
+            .await
+            .unwrap();
+        assert_eq!((supplied.supply, supplied.minted), (10000, 1000));
+
+        let entry = sft(State(server.clone()), Path(id(1))).await.unwrap();
+        assert_eq!(entry.0, sft_entry(1, "alice"));
+        assert!(matches!(
+            sft(State(server.clone()), Path("bad".to_string())).await,
+            Err(ServerError::BadRequest(_))
+        ));
+
+        let owned = sfts_by_owner(State(server.clone()), Path("bob".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(owned.0, vec![sft_entry(2, "bob")]);
+
+        let merged = lineage(State(server.clone()), Path(id(2))).await.unwrap();
+        assert_eq!(merged.sources, vec![inscription_id(3), inscription_id(4)]);
+        let minted = lineage(State(server.clone()), Path(id(1))).await.unwrap();
+        assert!(minted.sources.is_empty());
+        assert!(matches!(
+            lineage(State(server), Path(id(5))).await,
+            Err(ServerError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rolled_back() {
+        let (server, mut state) = server_state();
+
+        state.rollback_block().unwrap();
+        *server.state.write().unwrap() = state;
+        let server = Arc::new(server);
+
+        assert!(matches!(
+            sft(State(server.clone()), Path(inscription_id(1).to_string())).await,
+            Err(ServerError::NotFound(_))
+        ));
+        let owned = sfts_by_owner(State(server), Path("bob".to_string()))
+            .await
+            .unwrap();
+        assert!(owned.0.is_empty());
+    }
+}
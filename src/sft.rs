@@ -1,17 +1,138 @@
+use crate::cbor;
+use crate::compression::Compression;
 use crate::operation::{MintRecord, SplitRecord};
-use anyhow::{ensure, Result};
+use crate::tick::normalize_tick;
+use anyhow::{anyhow, ensure, Result};
 use ciborium::Value;
+use ord::InscriptionId;
+use primitive_types::H256;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct Content {
     pub content_type: String,
     pub body: Vec<u8>,
+    // The compression of the body, e.g. "br" or "gzip", the body is stored as inscribed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    // The inscription which has the content, it is inscribed as an ord delegate instead of
+    // the body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegate: Option<Delegate>,
+}
+
+/// The delegate inscription of a content and whether the content is copied from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delegate {
+    /// The content is empty until it is resolved from the delegate.
+    Unresolved(InscriptionId),
+    Resolved(InscriptionId),
+}
+
+impl Delegate {
+    pub fn inscription_id(&self) -> InscriptionId {
+        match self {
+            Delegate::Unresolved(inscription_id) | Delegate::Resolved(inscription_id) => {
+                *inscription_id
+            }
+        }
+    }
+}
+
+/// The resolved contents are compared by the content regardless of the delegate,
+/// so a delegated content is the same as the content it points to.
+impl PartialEq for Content {
+    fn eq(&self, other: &Self) -> bool {
+        self.content_type == other.content_type
+            && self.body == other.body
+            && self.content_encoding == other.content_encoding
+            && ((self.is_resolved() && other.is_resolved())
+                || self.delegate_id() == other.delegate_id())
+    }
 }
 
 impl Content {
     pub fn new(content_type: String, body: Vec<u8>) -> Self {
-        Self { content_type, body }
+        Self {
+            content_type,
+            body,
+            content_encoding: None,
+            delegate: None,
+        }
+    }
+
+    /// Compress the body with the given compression.
+    pub fn compress(content_type: String, body: &[u8], compression: Compression) -> Result<Self> {
+        Ok(Self {
+            content_type,
+            body: compression.compress(body)?,
+            content_encoding: Some(compression.as_str().to_string()),
+            delegate: None,
+        })
+    }
+
+    /// The unresolved content of the delegate inscription.
+    pub fn delegated(delegate: InscriptionId) -> Self {
+        Self {
+            delegate: Some(Delegate::Unresolved(delegate)),
+            ..Self::new(String::new(), vec![])
+        }
+    }
+
+    /// The same content delegated to the inscription which has it, `inscription_id` is
+    /// the inscription of this content, it is used unless the content is already delegated.
+    pub fn delegate_to(&self, inscription_id: InscriptionId) -> Self {
+        let inscription_id = self.delegate_id().unwrap_or(inscription_id);
+        let delegate = if self.is_resolved() {
+            Delegate::Resolved(inscription_id)
+        } else {
+            Delegate::Unresolved(inscription_id)
+        };
+        Self {
+            delegate: Some(delegate),
+            ..self.clone()
+        }
+    }
+
+    pub fn delegate_id(&self) -> Option<InscriptionId> {
+        self.delegate.map(|delegate| delegate.inscription_id())
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        !matches!(self.delegate, Some(Delegate::Unresolved(_)))
+    }
+
+    /// Resolve the delegated content with the content of the delegate inscription,
+    /// the delegate must have the content itself, so the delegates are not chained.
+    pub fn resolve(&mut self, delegate_content: Option<Content>) -> Result<()> {
+        let delegate = match self.delegate {
+            Some(Delegate::Unresolved(delegate)) => delegate,
+            _ => return Ok(()),
+        };
+        let delegate_content = delegate_content
+            .filter(|content| content.delegate.is_none())
+            .ok_or_else(|| anyhow!("The delegate inscription {} has no content", delegate))?;
+        *self = Self {
+            delegate: Some(Delegate::Resolved(delegate)),
+            ..delegate_content
+        };
+        Ok(())
+    }
+
+    /// The decompressed body, it fails if the body is larger than `limit` bytes.
+    pub fn decoded_body(&self, limit: u64) -> Result<Vec<u8>> {
+        let body = match &self.content_encoding {
+            Some(encoding) => Compression::from_str(encoding)?.decompress(&self.body, limit)?,
+            None => self.body.clone(),
+        };
+        ensure!(
+            body.len() as u64 <= limit,
+            "The content exceeds the limit of {} bytes",
+            limit
+        );
+        Ok(body)
     }
 
     pub fn text(body: String) -> Self {
@@ -67,24 +188,91 @@ impl SFT {
     }
 
     pub fn merge(&mut self, sft: SFT) -> Result<()> {
-        if self.tick != sft.tick {
+        self.ensure_same_kind(&sft)?;
+        self.amount += sft.amount;
+        Ok(())
+    }
+
+    /// Ensure the two SFTs have the same tick, attributes and content, so they can be split or merged.
+    /// The ticks are compared in the normalized form, the same as the index.
+    pub fn ensure_same_kind(&self, sft: &SFT) -> Result<()> {
+        if normalize_tick(&self.tick) != normalize_tick(&sft.tick) {
             return Err(anyhow::anyhow!("SFTs have different ticks"));
         }
-        if self.attributes != sft.attributes {
+        if !cbor::canonical_eq_opt(&self.attributes, &sft.attributes) {
             return Err(anyhow::anyhow!("SFTs have different attributes"));
         }
         if self.content != sft.content {
             return Err(anyhow::anyhow!("SFTs have different content"));
         }
-        self.amount += sft.amount;
         Ok(())
     }
 
+    /// The hash of the SFT attributes and content, the amount is not included.
+    /// It identifies the SFTs generated with the same attributes, regardless of the key order.
+    pub fn attributes_hash(&self) -> H256 {
+        let mut map = vec![];
+        if let Some(attributes) = &self.attributes {
+            map.push((Value::Text("attributes".to_string()), attributes.clone()));
+        }
+        if let Some(content) = &self.content {
+            let mut content_map = vec![
+                (
+                    Value::Text("content_type".to_string()),
+                    Value::Text(content.content_type.clone()),
+                ),
+                (
+                    Value::Text("body".to_string()),
+                    Value::Bytes(content.body.clone()),
+                ),
+            ];
+            if let Some(content_encoding) = &content.content_encoding {
+                content_map.push((
+                    Value::Text("content_encoding".to_string()),
+                    Value::Text(content_encoding.clone()),
+                ));
+            }
+            map.push((Value::Text("content".to_string()), Value::Map(content_map)));
+        }
+
+        cbor::canonical_hash(&Value::Map(map))
+    }
+
     pub fn to_mint_record(&self) -> MintRecord {
-        MintRecord { sft: self.clone() }
+        MintRecord {
+            sft: self.clone(),
+            user_input: None,
+        }
     }
 
-    pub fn to_split_record(&self) -> SplitRecord {
-        SplitRecord { sft: self.clone() }
+    pub fn to_split_record(&self, original: InscriptionId) -> SplitRecord {
+        SplitRecord {
+            sft: self.clone(),
+            original: vec![original],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_same_kind() {
+        let sft = SFT::new("BITS".to_string(), 1000, None, None);
+        assert!(sft
+            .ensure_same_kind(&SFT::new("bits".to_string(), 10, None, None))
+            .is_ok());
+        assert!(sft
+            .ensure_same_kind(&SFT::new("BITZ".to_string(), 10, None, None))
+            .is_err());
+        assert!(sft
+            .ensure_same_kind(&SFT::new(
+                "BITS".to_string(),
+                10,
+                Some(Value::Bool(true)),
+                None
+            ))
+            .is_err());
     }
 }
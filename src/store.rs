@@ -0,0 +1,577 @@
+use crate::generator::metadata::GeneratorMetadata;
+use crate::indexer::state::{BlockUndo, Changes};
+use crate::indexer::{IndexerState, SFTEntry, TickEntry};
+use crate::operation::Operation;
+use crate::tick::normalize_tick;
+use anyhow::{bail, ensure, Result};
+use bitcoin::BlockHash;
+use ord::InscriptionId;
+use redb::{
+    Database, DatabaseError, ReadableTable, Table, TableDefinition, TableHandle, WriteTransaction,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+
+/// The version of the store layout, bump it when the tables or the encoding of the values change.
+pub const SCHEMA_VERSION: u64 = 1;
+
+// Another process may hold the database, wait for it to finish.
+const OPEN_RETRIES: u32 = 100;
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+type BytesTable = TableDefinition<'static, &'static str, &'static [u8]>;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+const HEIGHT_KEY: &str = "height";
+const TIP_KEY: &str = "tip";
+const FINAL_BLOCK_KEY: &str = "final_block";
+
+// Store metadata, such as the schema version
+const META: TableDefinition<&str, u64> = TableDefinition::new("META");
+// Inscription id -> decoded Bitseed operation of the indexed inscription
+const OPERATIONS: BytesTable = TableDefinition::new("OPERATIONS");
+// SFT inscription id -> the inscription ids of the SFTs burned to create it by split or merge
+const LINEAGE: BytesTable = TableDefinition::new("LINEAGE");
+// Generator inscription id -> wasm bytecode
+const GENERATORS: BytesTable = TableDefinition::new("GENERATORS");
+// Generator inscription id -> the metadata of the generator, it is checked with the bytecode
+const GENERATOR_METADATA: BytesTable = TableDefinition::new("GENERATOR_METADATA");
+// The height, the tip and the final block of the index
+const INDEXER: BytesTable = TableDefinition::new("INDEXER");
+// Normalized tick -> the deploy and the mint state of the tick
+const TICKS: BytesTable = TableDefinition::new("TICKS");
+// SFT inscription id -> the SFT and its location
+const SFTS: BytesTable = TableDefinition::new("SFTS");
+// Invalid inscription id -> the reason
+const INVALID: BytesTable = TableDefinition::new("INVALID");
+// Block height -> the undo log of the block which is not final yet
+const BLOCKS: TableDefinition<u64, &[u8]> = TableDefinition::new("BLOCKS");
+
+// The tables of the indexer state, they are rebuilt by a reindex or a deep reorg.
+const INDEX_TABLES: [BytesTable; 6] = [OPERATIONS, LINEAGE, INDEXER, TICKS, SFTS, INVALID];
+
+/// Store persists the indexer state and the decoded Bitseed operations on disk,
+/// so the queries do not need to fetch and decode the transactions from bitcoind again.
+///
+/// The database is opened once and held by the store, another `bitseed` process can not use
+/// the same index until the store is dropped.
+#[derive(Clone)]
+pub struct Store {
+    db: Arc<Database>,
+}
+
+impl Store {
+    /// Open the store at `path`, it is created if it does not exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = Self::create(path)?;
+        match store.schema_version()? {
+            Some(SCHEMA_VERSION) => {}
+            Some(version) => bail!(
+                "The index at {} has schema version {}, but version {} is required, run `bitseed reindex` to rebuild it",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            ),
+            None => store.init()?,
+        }
+        Ok(store)
+    }
+
+    /// Open the store at `path` and drop all the stored data, the index is rebuilt from the chain.
+    pub fn reindex(path: &Path) -> Result<Self> {
+        let store = Self::create(path)?;
+        info!("reindex the store at {}", path.display());
+        store.init()?;
+        Ok(store)
+    }
+
+    fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            db: Arc::new(Self::database(path)?),
+        })
+    }
+
+    fn database(path: &Path) -> Result<Database> {
+        let mut retries = 0;
+        loop {
+            match Database::create(path) {
+                Ok(db) => return Ok(db),
+                Err(DatabaseError::DatabaseAlreadyOpen) if retries < OPEN_RETRIES => {
+                    retries += 1;
+                    thread::sleep(OPEN_RETRY_INTERVAL);
+                }
+                Err(DatabaseError::DatabaseAlreadyOpen) => bail!(
+                    "The index at {} is used by another bitseed process",
+                    path.display()
+                ),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Drop all the tables and write the current schema version.
+    fn init(&self) -> Result<()> {
+        let wtx = self.db.begin_write()?;
+        for table in INDEX_TABLES
+            .into_iter()
+            .chain([GENERATORS, GENERATOR_METADATA])
+        {
+            wtx.delete_table(table)?;
+            wtx.open_table(table)?;
+        }
+        wtx.delete_table(BLOCKS)?;
+        wtx.open_table(BLOCKS)?;
+        wtx.delete_table(META)?;
+        wtx.open_table(META)?
+            .insert(SCHEMA_VERSION_KEY, SCHEMA_VERSION)?;
+        wtx.commit()?;
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> Result<Option<u64>> {
+        let rtx = self.db.begin_read()?;
+        let table = match rtx.open_table(META) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let version = table.get(SCHEMA_VERSION_KEY)?.map(|v| v.value());
+        Ok(version)
+    }
+
+    /// Drop the indexer state, the generators are kept since they do not depend on the chain tip.
+    pub fn reset_index(&self) -> Result<()> {
+        let wtx = self.db.begin_write()?;
+        for table in INDEX_TABLES {
+            wtx.delete_table(table)?;
+            wtx.open_table(table)?;
+        }
+        wtx.delete_table(BLOCKS)?;
+        wtx.open_table(BLOCKS)?;
+        wtx.commit()?;
+        Ok(())
+    }
+
+    /// The last indexed block of the stored state.
+    pub fn tip(&self) -> Result<Option<(u64, BlockHash)>> {
+        Ok(self.get(INDEXER, TIP_KEY)?.flatten())
+    }
+
+    /// Load the indexer state, the entries are rebuilt from the tables.
+    pub fn load_state(&self) -> Result<IndexerState> {
+        let rtx = self.db.begin_read()?;
+
+        let indexer = rtx.open_table(INDEXER)?;
+        let height: Option<u64> = Self::read(INDEXER, &indexer, HEIGHT_KEY)?.flatten();
+        let final_block: Option<(u64, BlockHash)> =
+            Self::read(INDEXER, &indexer, FINAL_BLOCK_KEY)?.flatten();
+
+        let ticks: BTreeMap<String, TickEntry> = Self::read_all(TICKS, &rtx.open_table(TICKS)?)?
+            .into_iter()
+            .collect();
+        let sfts: Vec<SFTEntry> = Self::read_all::<SFTEntry>(SFTS, &rtx.open_table(SFTS)?)?
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect();
+        let invalid = Self::read_ids(INVALID, &rtx.open_table(INVALID)?)?;
+        let lineage = Self::read_ids(LINEAGE, &rtx.open_table(LINEAGE)?)?;
+
+        let mut blocks = VecDeque::new();
+        for row in rtx.open_table(BLOCKS)?.iter()? {
+            let (height, value) = row?;
+            let block: BlockUndo =
+                Self::decode(BLOCKS.name(), &height.value().to_string(), value.value())?;
+            blocks.push_back(block);
+        }
+
+        Ok(IndexerState::restore(
+            height,
+            final_block,
+            ticks,
+            sfts,
+            invalid,
+            lineage,
+            blocks,
+        ))
+    }
+
+    /// Write the changed entries of the state in one transaction. `base` is the tip of the state
+    /// before the changes, the write fails if another process has updated the store since then.
+    pub fn save_changes(
+        &self,
+        base: Option<(u64, BlockHash)>,
+        state: &IndexerState,
+        changes: Changes,
+    ) -> Result<()> {
+        let wtx = self.db.begin_write()?;
+        {
+            let mut indexer = wtx.open_table(INDEXER)?;
+            let stored_tip: Option<(u64, BlockHash)> =
+                Self::read(INDEXER, &indexer, TIP_KEY)?.flatten();
+            ensure!(
+                stored_tip == base,
+                "The index is updated by another process, its tip is {:?} instead of {:?}",
+                stored_tip,
+                base
+            );
+            Self::write(&mut indexer, HEIGHT_KEY, &state.height())?;
+            Self::write(&mut indexer, TIP_KEY, &state.tip())?;
+            Self::write(&mut indexer, FINAL_BLOCK_KEY, &state.final_block())?;
+        }
+
+        Self::write_changes(&wtx, TICKS, changes.ticks, |tick| {
+            state.get_tick(tick).cloned()
+        })?;
+        Self::write_changes(&wtx, SFTS, changes.sfts, |id| state.get_sft(id).cloned())?;
+        Self::write_changes(&wtx, INVALID, changes.invalid, |id| {
+            state.get_invalid(id).cloned()
+        })?;
+        Self::write_changes(&wtx, LINEAGE, changes.lineage, |id| {
+            state.get_lineage(id).cloned()
+        })?;
+        {
+            let mut table = wtx.open_table(OPERATIONS)?;
+            for (inscription_id, operation) in changes.operations {
+                match operation {
+                    Some(operation) => {
+                        Self::write(&mut table, &inscription_id.to_string(), &operation)?
+                    }
+                    None => {
+                        table.remove(inscription_id.to_string().as_str())?;
+                    }
+                }
+            }
+        }
+        {
+            let mut table = wtx.open_table(BLOCKS)?;
+            for height in changes.blocks {
+                match state.get_block(height) {
+                    Some(block) => {
+                        table.insert(height, Self::encode(block)?.as_slice())?;
+                    }
+                    None => {
+                        table.remove(height)?;
+                    }
+                }
+            }
+        }
+
+        wtx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_tick(&self, tick: &str) -> Result<Option<TickEntry>> {
+        self.get(TICKS, &normalize_tick(tick))
+    }
+
+    pub fn get_operation(&self, inscription_id: &InscriptionId) -> Result<Option<Operation>> {
+        self.get(OPERATIONS, &inscription_id.to_string())
+    }
+
+    pub fn get_sft(&self, inscription_id: &InscriptionId) -> Result<Option<SFTEntry>> {
+        self.get(SFTS, &inscription_id.to_string())
+    }
+
+    /// Get the SFT entries which match the filter, all the entries are scanned.
+    pub fn get_sfts(&self, filter: impl Fn(&SFTEntry) -> bool) -> Result<Vec<SFTEntry>> {
+        let rtx = self.db.begin_read()?;
+        Ok(Self::read_all::<SFTEntry>(SFTS, &rtx.open_table(SFTS)?)?
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| filter(entry))
+            .collect())
+    }
+
+    /// Get the reason why the inscription is an invalid Bitseed operation.
+    pub fn get_invalid(&self, inscription_id: &InscriptionId) -> Result<Option<String>> {
+        self.get(INVALID, &inscription_id.to_string())
+    }
+
+    /// Get the inscription ids of the SFTs which are burned to create the SFT.
+    pub fn get_lineage(
+        &self,
+        inscription_id: &InscriptionId,
+    ) -> Result<Option<Vec<InscriptionId>>> {
+        self.get(LINEAGE, &inscription_id.to_string())
+    }
+
+    /// Get the metadata and the bytecode of the generator.
+    pub fn get_generator(
+        &self,
+        inscription_id: &InscriptionId,
+    ) -> Result<Option<(GeneratorMetadata, Vec<u8>)>> {
+        let key = inscription_id.to_string();
+        let rtx = self.db.begin_read()?;
+        let bytecode = rtx
+            .open_table(GENERATORS)?
+            .get(key.as_str())?
+            .map(|v| v.value().to_vec());
+        let metadata: Option<GeneratorMetadata> = Self::read(
+            GENERATOR_METADATA,
+            &rtx.open_table(GENERATOR_METADATA)?,
+            &key,
+        )?;
+        Ok(metadata.zip(bytecode))
+    }
+
+    pub fn insert_generator(
+        &self,
+        inscription_id: &InscriptionId,
+        metadata: &GeneratorMetadata,
+        bytecode: &[u8],
+    ) -> Result<()> {
+        let key = inscription_id.to_string();
+        let wtx = self.db.begin_write()?;
+        wtx.open_table(GENERATORS)?.insert(key.as_str(), bytecode)?;
+        wtx.open_table(GENERATOR_METADATA)?
+            .insert(key.as_str(), Self::encode(metadata)?.as_slice())?;
+        wtx.commit()?;
+        Ok(())
+    }
+
+    fn get<T: DeserializeOwned>(&self, definition: BytesTable, key: &str) -> Result<Option<T>> {
+        let rtx = self.db.begin_read()?;
+        Self::read(definition, &rtx.open_table(definition)?, key)
+    }
+
+    fn read<T: DeserializeOwned>(
+        definition: BytesTable,
+        table: &impl ReadableTable<&'static str, &'static [u8]>,
+        key: &str,
+    ) -> Result<Option<T>> {
+        match table.get(key)? {
+            Some(value) => Ok(Some(Self::decode(definition.name(), key, value.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_all<T: DeserializeOwned>(
+        definition: BytesTable,
+        table: &impl ReadableTable<&'static str, &'static [u8]>,
+    ) -> Result<Vec<(String, T)>> {
+        let mut values = Vec::new();
+        for row in table.iter()? {
+            let (key, value) = row?;
+            let key = key.value().to_string();
+            let value = Self::decode(definition.name(), &key, value.value())?;
+            values.push((key, value));
+        }
+        Ok(values)
+    }
+
+    fn read_ids<T: DeserializeOwned>(
+        definition: BytesTable,
+        table: &impl ReadableTable<&'static str, &'static [u8]>,
+    ) -> Result<BTreeMap<InscriptionId, T>> {
+        Self::read_all(definition, table)?
+            .into_iter()
+            .map(|(key, value)| Ok((InscriptionId::from_str(&key)?, value)))
+            .collect()
+    }
+
+    fn write<T: Serialize + ?Sized>(
+        table: &mut Table<&'static str, &'static [u8]>,
+        key: &str,
+        value: &T,
+    ) -> Result<()> {
+        table.insert(key, Self::encode(value)?.as_slice())?;
+        Ok(())
+    }
+
+    /// Write the current values of the changed keys, the keys without a value are removed.
+    fn write_changes<K: ToString, T: Serialize>(
+        wtx: &WriteTransaction,
+        definition: BytesTable,
+        keys: impl IntoIterator<Item = K>,
+        current: impl Fn(&K) -> Option<T>,
+    ) -> Result<()> {
+        let mut table = wtx.open_table(definition)?;
+        for key in keys {
+            match current(&key) {
+                Some(value) => Self::write(&mut table, &key.to_string(), &value)?,
+                None => {
+                    table.remove(key.to_string().as_str())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(table: &str, key: &str, bytes: &[u8]) -> Result<T> {
+        match ciborium::from_reader(bytes) {
+            Ok(value) => Ok(value),
+            Err(e) => bail!(
+                "Failed to decode {} of {} from the index: {}",
+                key,
+                table,
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::{DeployRecord, MintRecord};
+    use crate::sft::SFT;
+    use bitcoin::hashes::Hash;
+    use bitcoin::OutPoint;
+    use ordinals::SatPoint;
+
+    fn inscription_id(index: u32) -> InscriptionId {
+        InscriptionId::from_str(&format!(
+            "6f55475ce65054aa8371d618d217da8c9a764cecdaf4debcbce8d6312fe6b4d8i{}",
+            index
+        ))
+        .unwrap()
+    }
+
+    fn sft_entry(index: u32) -> SFTEntry {
+        SFTEntry {
+            inscription_id: inscription_id(index),
+            sft: SFT::new("BITS".to_string(), 1000, None, None),
+            indexer_attributes: None,
+            satpoint: SatPoint {
+                outpoint: OutPoint {
+                    txid: inscription_id(index).txid,
+                    vout: 0,
+                },
+                offset: 0,
+            },
+            owner: None,
+        }
+    }
+
+    fn block_hash(height: u64) -> BlockHash {
+        BlockHash::hash(&height.to_le_bytes())
+    }
+
+    fn save_block(store: &Store, state: &mut IndexerState, height: u64) -> Result<()> {
+        let base = state.tip();
+        state.commit_block(height, block_hash(height), 6);
+        let changes = state.take_changes();
+        store.save_changes(base, state, changes)
+    }
+
+    #[test]
+    fn test_save_changes() {
+        let dir = std::env::temp_dir().join(format!("bitseed-store-{}", uuid::Uuid::new_v4()));
+        let store = Store::open(&dir.join("index.redb")).unwrap();
+        let mut state = store.load_state().unwrap();
+        assert_eq!(state.tip(), None);
+
+        let deploy = DeployRecord::new_deploy_record(
+            "BITS".to_string(),
+            10000,
+            None,
+            None,
+            0,
+            false,
+            vec![],
+        );
+        state.insert_tick(inscription_id(0), deploy, Some(sft_entry(0).satpoint));
+        save_block(&store, &mut state, 1).unwrap();
+
+        state.insert_sft(sft_entry(1));
+        state.insert_lineage(inscription_id(1), vec![inscription_id(3)]);
+        state.insert_operation(
+            inscription_id(1),
+            Operation::Mint(MintRecord {
+                sft: sft_entry(1).sft,
+                user_input: None,
+            }),
+        );
+        save_block(&store, &mut state, 2).unwrap();
+
+        let loaded = store.load_state().unwrap();
+        assert_eq!(loaded.tip(), Some((2, block_hash(2))));
+        assert_eq!(loaded.height(), Some(2));
+        assert_eq!(loaded.get_tick("bits"), state.get_tick("BITS"));
+        assert_eq!(loaded.get_sft(&inscription_id(1)), Some(&sft_entry(1)));
+        assert_eq!(
+            loaded
+                .ticks_at_outpoint(&sft_entry(0).satpoint.outpoint)
+                .len(),
+            1
+        );
+        assert_eq!(
+            loaded
+                .sfts_at_outpoint(&sft_entry(1).satpoint.outpoint)
+                .len(),
+            1
+        );
+        assert_eq!(
+            store.get_tick("Bits").unwrap(),
+            state.get_tick("BITS").cloned()
+        );
+        assert!(store.get_operation(&inscription_id(1)).unwrap().is_some());
+        assert_eq!(
+            store.get_sft(&inscription_id(1)).unwrap(),
+            Some(sft_entry(1))
+        );
+        assert_eq!(
+            store
+                .get_sfts(|entry| entry.satpoint.outpoint == sft_entry(1).satpoint.outpoint)
+                .unwrap(),
+            vec![sft_entry(1)]
+        );
+        assert_eq!(
+            store.get_lineage(&inscription_id(1)).unwrap(),
+            Some(vec![inscription_id(3)])
+        );
+
+        // The rollback removes the entries and the operations of the block.
+        let mut loaded = loaded;
+        let base = loaded.tip();
+        loaded.rollback_block().unwrap();
+        let changes = loaded.take_changes();
+        store.save_changes(base, &loaded, changes).unwrap();
+
+        let rolled_back = store.load_state().unwrap();
+        assert_eq!(rolled_back.tip(), Some((1, block_hash(1))));
+        assert!(rolled_back.get_sft(&inscription_id(1)).is_none());
+        assert!(rolled_back.get_tick("BITS").is_some());
+        assert!(store.get_operation(&inscription_id(1)).unwrap().is_none());
+        assert!(store.get_lineage(&inscription_id(1)).unwrap().is_none());
+
+        // The stale state can not overwrite the updates of another process.
+        assert!(save_block(&store, &mut state, 3).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_generator() {
+        let dir = std::env::temp_dir().join(format!("bitseed-store-{}", uuid::Uuid::new_v4()));
+        let store = Store::open(&dir.join("index.redb")).unwrap();
+        assert!(store.get_generator(&inscription_id(0)).unwrap().is_none());
+
+        let bytecode = b"\0asm".to_vec();
+        let metadata = GeneratorMetadata::new("test".to_string(), &bytecode);
+        store
+            .insert_generator(&inscription_id(0), &metadata, &bytecode)
+            .unwrap();
+        assert_eq!(
+            store.get_generator(&inscription_id(0)).unwrap(),
+            Some((metadata, bytecode))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use crate::GENERATOR_TICK;
+use anyhow::{ensure, Result};
+
+pub const MIN_TICK_LENGTH: usize = 4;
+pub const MAX_TICK_LENGTH: usize = 32;
+
+/// The ticks which can not be deployed.
+pub const RESERVED_TICKS: &[&str] = &[GENERATOR_TICK];
+
+/// Ticks are case-insensitive, the normalized form is upper case.
+pub fn normalize_tick(tick: &str) -> String {
+    tick.to_uppercase()
+}
+
+/// Validate the tick name and return the normalized tick.
+pub fn validate_tick(tick: &str) -> Result<String> {
+    let len = tick.chars().count();
+    ensure!(
+        (MIN_TICK_LENGTH..=MAX_TICK_LENGTH).contains(&len),
+        "The tick {:?} must be {}-{} characters",
+        tick,
+        MIN_TICK_LENGTH,
+        MAX_TICK_LENGTH
+    );
+    ensure!(
+        tick.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        "The tick {:?} can only contain letters, digits, '_' and '-'",
+        tick
+    );
+
+    let normalized = normalize_tick(tick);
+    ensure!(
+        !RESERVED_TICKS
+            .iter()
+            .any(|reserved| normalize_tick(reserved) == normalized),
+        "The tick {:?} is reserved",
+        tick
+    );
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_tick() {
+        assert_eq!(validate_tick("bits").unwrap(), "BITS");
+        assert_eq!(validate_tick("Move_2-Earn").unwrap(), "MOVE_2-EARN");
+        assert_eq!(
+            validate_tick(&"a".repeat(MAX_TICK_LENGTH)).unwrap(),
+            "A".repeat(MAX_TICK_LENGTH)
+        );
+
+        assert!(validate_tick("bit").is_err());
+        assert!(validate_tick(&"a".repeat(MAX_TICK_LENGTH + 1)).is_err());
+        assert!(validate_tick("bi ts").is_err());
+        assert!(validate_tick("bits!").is_err());
+        assert!(validate_tick("比特种子").is_err());
+        assert!(validate_tick("generator").is_err());
+        assert!(validate_tick("Generator").is_err());
+    }
+}
@@ -1,18 +1,27 @@
+use crate::error::{self, InvalidOperation};
+use crate::generator::wasm::cache::ModuleCache;
+use crate::indexer::IndexerOptions;
+use crate::inscription;
 use crate::operation::Operation;
-use anyhow::{anyhow, bail, Result};
+use crate::sft::Content;
+use crate::store::Store;
+use anyhow::{anyhow, bail, ensure, Result};
 use bitcoin::Address;
 use bitcoin::OutPoint;
 use bitcoin::TxOut;
 use bitcoincore_rpc::RpcApi;
 use clap::Parser;
+use once_cell::sync::OnceCell;
 use ord::inscriptions::ParsedEnvelope;
 use ord::Chain;
+use ord::Inscription;
 use ord::InscriptionId;
 use ord::Options;
 use ordinals::SatPoint;
 use reqwest::Url;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[derive(Debug, Clone, Parser)]
@@ -30,11 +39,17 @@ pub struct WalletOption {
 
     #[clap(flatten)]
     pub chain_options: Options,
+
+    #[clap(flatten)]
+    pub index_options: IndexerOptions,
 }
 /// Wallet is a wrapper around ord::wallet::Wallet.
 #[derive(Clone)]
 pub struct Wallet {
     ord_wallet: Arc<ord::wallet::Wallet>,
+    index_options: IndexerOptions,
+    store: Arc<OnceCell<Store>>,
+    module_cache: Arc<OnceCell<ModuleCache>>,
 }
 
 impl Wallet {
@@ -49,9 +64,56 @@ impl Wallet {
 
         Ok(Self {
             ord_wallet: Arc::new(wallet),
+            index_options: opt.index_options,
+            store: Arc::new(OnceCell::new()),
+            module_cache: Arc::new(OnceCell::new()),
+        })
+    }
+
+    pub fn index_options(&self) -> &IndexerOptions {
+        &self.index_options
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.index_options.index_path.clone().unwrap_or_else(|| {
+            PathBuf::from(".bitseed")
+                .join(self.chain().to_string())
+                .join("index.redb")
         })
     }
 
+    /// The compiled generator modules are cached next to the index, they are only kept
+    /// in memory if the index path has no directory.
+    pub fn module_cache_dir(&self) -> Option<PathBuf> {
+        self.index_path()
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| dir.join("modules"))
+    }
+
+    /// The cache of the compiled generator modules, shared by the clones of the wallet.
+    pub fn module_cache(&self) -> &ModuleCache {
+        self.module_cache
+            .get_or_init(|| ModuleCache::new(self.module_cache_dir()))
+    }
+
+    /// The on-disk store of the Bitseed index, it is opened on the first use.
+    pub fn store(&self) -> Result<&Store> {
+        self.store
+            .get_or_try_init(|| Store::open(&self.index_path()))
+    }
+
+    /// Drop the stored index and open an empty store, it must be called before the store is used.
+    pub fn reindex_store(&self) -> Result<&Store> {
+        let mut reindexed = false;
+        let store = self.store.get_or_try_init(|| {
+            reindexed = true;
+            Store::reindex(&self.index_path())
+        })?;
+        ensure!(reindexed, "The store is already opened");
+        Ok(store)
+    }
+
     pub fn bitcoin_client(&self) -> Result<&bitcoincore_rpc::Client> {
         Ok(&self.ord_wallet.bitcoin_client)
     }
@@ -182,6 +244,73 @@ impl Wallet {
         Ok(client.get_raw_transaction(txid, None)?)
     }
 
+    /// Get the transaction, None if bitcoind does not know it. With the txindex, every confirmed
+    /// transaction is known, so a missing transaction is not an error of bitcoind.
+    pub fn get_raw_transaction_opt(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Result<Option<bitcoin::Transaction>> {
+        let client = self.bitcoin_client()?;
+        match client.get_raw_transaction(txid, None) {
+            Ok(tx) => Ok(Some(tx)),
+            Err(e) if Self::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The height of the block which confirms the transaction, None if the transaction is in
+    /// the mempool, in a block which is not in the best chain, or bitcoind does not know it.
+    pub fn get_transaction_height(&self, txid: &bitcoin::Txid) -> Result<Option<u64>> {
+        let client = self.bitcoin_client()?;
+        let block_hash = match client.get_raw_transaction_info(txid, None) {
+            Ok(info) => info.blockhash,
+            Err(e) if Self::is_not_found(&e) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(block_hash) = block_hash else {
+            return Ok(None);
+        };
+        let header = client.get_block_header_info(&block_hash)?;
+        Ok((header.confirmations > 0).then_some(header.height as u64))
+    }
+
+    /// The inscription must be revealed at or before the block `height` which refers to it,
+    /// otherwise the operation would depend on when it is indexed.
+    pub fn check_confirmed(&self, inscription_id: InscriptionId, height: u64) -> Result<()> {
+        let confirmed_height = self.get_transaction_height(&inscription_id.txid)?;
+        Self::ensure_confirmed(inscription_id, confirmed_height, height)
+    }
+
+    pub(crate) fn ensure_confirmed(
+        inscription_id: InscriptionId,
+        confirmed_height: Option<u64>,
+        height: u64,
+    ) -> Result<()> {
+        match confirmed_height {
+            Some(confirmed_height) if confirmed_height <= height => Ok(()),
+            Some(confirmed_height) => Err(InvalidOperation::new(format!(
+                "Inscription {} is revealed in block {}, after block {}",
+                inscription_id, confirmed_height, height
+            ))
+            .into()),
+            None => Err(InvalidOperation::new(format!(
+                "Inscription {} is not confirmed",
+                inscription_id
+            ))
+            .into()),
+        }
+    }
+
+    /// Bitcoind returns -5 if it does not know the transaction or the block.
+    fn is_not_found(e: &bitcoincore_rpc::Error) -> bool {
+        matches!(
+            e,
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::error::Error::Rpc(
+                bitcoincore_rpc::jsonrpc::error::RpcError { code: -5, .. },
+            ))
+        )
+    }
+
     pub fn exists_utxo(&self, outpoint: &OutPoint) -> Result<bool> {
         Ok(self.get_unspent_outputs()?.contains_key(outpoint))
     }
@@ -213,15 +342,74 @@ impl Wallet {
         &self,
         inscription_id: InscriptionId,
     ) -> Result<Operation> {
-        let tx = self.get_raw_transaction(&inscription_id.txid)?;
+        self.get_operation_at(inscription_id, None)
+    }
+
+    /// Get the operation of the inscription, the inscription and its delegate which are not
+    /// stored must be confirmed at or before the block `height` if it is given.
+    pub fn get_operation_at(
+        &self,
+        inscription_id: InscriptionId,
+        height: Option<u64>,
+    ) -> Result<Operation> {
+        let store = self.store()?;
+        if let Some(operation) = store.get_operation(&inscription_id)? {
+            return Ok(operation);
+        }
+
+        // Only the indexer stores the operations, so the store does not keep the operations
+        // of the blocks which are not indexed or rolled back.
+        if let Some(height) = height {
+            self.check_confirmed(inscription_id, height)?;
+        }
+        let operation = Operation::from_inscription(self.get_inscription(inscription_id)?)?;
+        let delegate_content = match operation.unresolved_delegate() {
+            Some(delegate) => self.get_delegate_content(delegate, height)?,
+            None => None,
+        };
+        operation
+            .resolve_content(delegate_content)
+            .map_err(error::invalid)
+    }
+
+    /// The content of the delegate inscription, it may be any inscription on chain. The stored
+    /// operations are indexed before the current block, the other delegates must be confirmed
+    /// at or before the block `height` if it is given.
+    pub fn get_delegate_content(
+        &self,
+        delegate: InscriptionId,
+        height: Option<u64>,
+    ) -> Result<Option<Content>> {
+        if let Some(operation) = self.store()?.get_operation(&delegate)? {
+            return Ok(operation.content().cloned());
+        }
+        if let Some(height) = height {
+            self.check_confirmed(delegate, height)?;
+        }
+        Ok(inscription::content(&self.get_inscription(delegate)?))
+    }
+
+    /// Get the inscription from its reveal transaction. An inscription which does not exist is
+    /// an invalid reference, the other errors of bitcoind are returned as they are.
+    pub fn get_inscription(&self, inscription_id: InscriptionId) -> Result<Inscription> {
+        let tx = self
+            .get_raw_transaction_opt(&inscription_id.txid)?
+            .ok_or_else(|| {
+                InvalidOperation::new(format!("Inscription {} not found", inscription_id))
+            })?;
         let inscriptions = ParsedEnvelope::from_transaction(&tx);
 
         let envelope = inscriptions
             .into_iter()
             .nth(inscription_id.index as usize)
-            .ok_or_else(|| anyhow!("Inscription not found in the transaction"))?;
-
-        Operation::from_inscription(envelope.payload)
+            .ok_or_else(|| {
+                InvalidOperation::new(format!(
+                    "Inscription {} not found in the transaction",
+                    inscription_id
+                ))
+            })?;
+
+        Ok(envelope.payload)
     }
 
     pub fn send_raw_transaction_v2<R: bitcoincore_rpc::RawTx>(
@@ -254,3 +442,25 @@ impl Wallet {
         Ok(tx_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{hashes::Hash, Txid};
+
+    #[test]
+    fn test_ensure_confirmed() {
+        let delegate = InscriptionId {
+            txid: Txid::all_zeros(),
+            index: 0,
+        };
+        assert!(Wallet::ensure_confirmed(delegate, Some(99), 100).is_ok());
+        assert!(Wallet::ensure_confirmed(delegate, Some(100), 100).is_ok());
+
+        // The delegate revealed in a later block, or still in the mempool, is invalid.
+        let later = Wallet::ensure_confirmed(delegate, Some(101), 100).unwrap_err();
+        assert!(error::is_invalid(&later));
+        let unconfirmed = Wallet::ensure_confirmed(delegate, None, 100).unwrap_err();
+        assert!(error::is_invalid(&unconfirmed));
+    }
+}
@@ -22,7 +22,7 @@ Feature: Bitseed CLI integration tests
     Then sleep: "5"
 
     # deploy
-    Then cmd bitseed: "deploy --fee-rate 1 --generator {{$.generator[-1].inscriptions[0].Id}} --tick bits --amount 210000000000 --deploy-args {"height":{"type":"range","data":{"min":1,"max":1000}}}"
+    Then cmd bitseed: "deploy --fee-rate 1 --generator {{$.generator[-1].inscriptions[0].Id}} --tick bits --amount 210000000000 --has-user-input --deploy-args {"height":{"type":"range","data":{"min":1,"max":1000}}}"
     Then assert: "'{{$.deploy[-1]}}' not_contains error"
 
     # mine a block